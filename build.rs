@@ -0,0 +1,715 @@
+//! Generates the Fivetran REST API client from `fivetran_openapi_v1.json`.
+//!
+//! Output is written to `$OUT_DIR/fivetran_api.rs` and included by
+//! `src/fivetran/api.rs`. It contains:
+//! - a struct or enum for every schema reachable from the API paths,
+//! - service-specific request/response models (the ones carrying `config`)
+//!   for services listed in [SERVICES],
+//! - an async function for every operation.
+//!
+//! The spec is not entirely regular, so a few conventions are hard-coded here.
+//! See [Generator::rust_type] for how schemas map to Rust types.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::{env, fs};
+
+use serde_json::{Map, Value, json};
+
+const SPEC: &str = "fivetran_openapi_v1.json";
+
+/// Services for which we generate typed models of connector and destination
+/// `config`. The spec defines ~700 of them; we only need a few.
+const SERVICES: &[&str] = &["postgres", "postgres_warehouse"];
+
+/// POST operations that can safely be repeated when a request fails.
+/// PATCH operations in this API only set fields, so all of them are retried.
+const IDEMPOTENT_POSTS: &[&str] = &[
+    "reload_connection_schema_config",
+    "run_setup_tests",
+    "run_destination_setup_tests",
+];
+
+/// Property names that stand for arbitrary keys. The spec describes maps
+/// (e.g. schema name -> schema config) as an object with a single property
+/// named like this.
+const MAP_PLACEHOLDERS: &[&str] = &["schema", "table", "column"];
+
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-changed={SPEC}");
+
+    let spec: Value = serde_json::from_str(&fs::read_to_string(SPEC).unwrap()).unwrap();
+
+    let mut generator = Generator::new(&spec);
+    generator.generate();
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("fivetran_api.rs");
+    fs::write(out_path, generator.out).unwrap();
+}
+
+struct Generator<'a> {
+    spec: &'a Value,
+    schemas: &'a Map<String, Value>,
+
+    out: String,
+
+    /// Names of Rust types that were already emitted.
+    emitted: BTreeSet<String>,
+
+    /// Structs that derive `Default`.
+    defaultable: BTreeSet<String>,
+
+    /// Enums by their variants, so identical enums are emitted as aliases of
+    /// the first one instead of being duplicated.
+    enums: HashMap<String, String>,
+
+    /// Schemas with a `service` discriminator, with the service-specific
+    /// variants that were generated for them.
+    variants: BTreeMap<String, Vec<String>>,
+}
+
+/// A property of a struct, possibly coming from one of its `allOf` parts.
+struct Field {
+    json_name: String,
+    schema: Value,
+    required: bool,
+
+    /// Prefix for names of inline types of this field. This is the type name
+    /// of the schema that defined the property.
+    prefix: String,
+
+    /// Property was defined by multiple `allOf` parts and merged.
+    merged: bool,
+}
+
+enum ResponseKind {
+    Empty,
+    Data(String),
+    /// Response of a discriminated base schema, the caller picks the variant.
+    Variant(String),
+    Page(String),
+}
+
+impl<'a> Generator<'a> {
+    fn new(spec: &'a Value) -> Self {
+        Generator {
+            spec,
+            schemas: spec["components"]["schemas"].as_object().unwrap(),
+            out: String::new(),
+            emitted: BTreeSet::new(),
+            defaultable: BTreeSet::new(),
+            enums: HashMap::new(),
+            variants: BTreeMap::new(),
+        }
+    }
+
+    fn generate(&mut self) {
+        writeln!(
+            self.out,
+            "// @generated by build.rs from {SPEC}. Do not edit.\n"
+        )
+        .unwrap();
+
+        let schemas = self.schemas;
+        for (name, schema) in schemas {
+            let Some(mapping) = schema["discriminator"]["mapping"].as_object() else {
+                continue;
+            };
+            let mut variants = Vec::new();
+            for service in SERVICES {
+                if let Some(r) = mapping.get(*service).and_then(Value::as_str) {
+                    variants.push(self.component(ref_name(r)));
+                }
+            }
+            self.variants.insert(name.clone(), variants);
+        }
+
+        let spec = self.spec;
+        let paths = spec["paths"].as_object().unwrap();
+        for (path, item) in paths {
+            for (method, op) in item.as_object().unwrap() {
+                self.operation(path, method, op);
+            }
+        }
+
+        let variants = std::mem::take(&mut self.variants);
+        for (base, variants) in variants {
+            let base = type_name(&base);
+            if !self.emitted.contains(&base) {
+                continue;
+            }
+            let mut s = String::new();
+            writeln!(
+                s,
+                "/// Implemented by [{base}] and its service-specific variants."
+            )
+            .unwrap();
+            writeln!(
+                s,
+                "pub trait {base}Like: Serialize + DeserializeOwned + std::fmt::Debug {{}}\n"
+            )
+            .unwrap();
+            writeln!(s, "impl {base}Like for {base} {{}}").unwrap();
+            for v in variants {
+                writeln!(s, "impl {base}Like for {v} {{}}").unwrap();
+            }
+            writeln!(s).unwrap();
+            self.out += &s;
+        }
+    }
+
+    /// Returns the Rust type for a schema from `components/schemas`,
+    /// emitting it if needed.
+    fn component(&mut self, name: &str) -> String {
+        let schema = &self.schemas[name];
+        if is_opaque(schema) {
+            return "serde_json::Value".into();
+        }
+        let ty = type_name(name);
+        if self.emitted.contains(&ty) {
+            return ty;
+        }
+        self.rust_type(schema, &ty)
+    }
+
+    /// Maps a schema to a Rust type:
+    /// - `$ref` to the referenced type,
+    /// - `enum` to a Rust enum (regardless of `type`, which is sometimes wrong),
+    /// - objects with properties (or `allOf`) to a struct,
+    /// - objects with only a [MAP_PLACEHOLDERS] property or
+    ///   `additionalProperties` to a `HashMap`,
+    /// - objects without any properties to `serde_json::Value`.
+    ///
+    /// Inline structs and enums are named `name`.
+    fn rust_type(&mut self, schema: &Value, name: &str) -> String {
+        if let Some(r) = schema["$ref"].as_str() {
+            return self.component(ref_name(r));
+        }
+        if schema.get("enum").is_some() {
+            return self.enum_type(schema, name);
+        }
+        if schema.get("oneOf").is_some() || schema.get("anyOf").is_some() {
+            return "serde_json::Value".into();
+        }
+
+        match schema["type"].as_str() {
+            Some("string") => "String".into(),
+            Some("integer") if schema["format"] == "int32" => "i32".into(),
+            Some("integer") => "i64".into(),
+            Some("number") => "f64".into(),
+            Some("boolean") => "bool".into(),
+            Some("array") => {
+                let item = self.rust_type(&schema["items"], &format!("{name}Item"));
+                format!("Vec<{item}>")
+            }
+            _ => {
+                let properties = schema["properties"].as_object();
+                if let Some(value) = map_placeholder(schema) {
+                    let value = self.rust_type(value, &format!("{name}Value"));
+                    format!("HashMap<String, {value}>")
+                } else if properties.is_some_and(|p| !p.is_empty()) || schema.get("allOf").is_some()
+                {
+                    self.struct_type(schema, name);
+                    name.to_string()
+                } else if let Some(value) = schema["additionalProperties"].as_object() {
+                    let value = self.rust_type(&Value::Object(value.clone()), name);
+                    format!("HashMap<String, {value}>")
+                } else {
+                    "serde_json::Value".into()
+                }
+            }
+        }
+    }
+
+    fn struct_type(&mut self, schema: &Value, name: &str) {
+        if !self.emitted.insert(name.to_string()) {
+            return;
+        }
+
+        let mut fields = Vec::new();
+        self.collect_fields(schema, name, &mut fields);
+
+        let mut rust_fields = Vec::new();
+        for field in &fields {
+            let type_prefix = if field.merged { name } else { &field.prefix };
+            let inline_name = format!("{type_prefix}{}", pascal_case(&field.json_name));
+            let ty = self.rust_type(&field.schema, &inline_name);
+            rust_fields.push(ty);
+        }
+
+        let derive_default = rust_fields.iter().zip(&fields).all(|(ty, f)| {
+            !f.required
+                || ty.starts_with("Vec<")
+                || ty.starts_with("HashMap<")
+                || matches!(
+                    ty.as_str(),
+                    "String" | "bool" | "i32" | "i64" | "f64" | "serde_json::Value"
+                )
+                || self.defaultable.contains(ty)
+        });
+        if derive_default {
+            self.defaultable.insert(name.to_string());
+        }
+
+        let mut s = String::new();
+        write_doc(&mut s, "", schema["description"].as_str());
+        let default = if derive_default { ", Default" } else { "" };
+        writeln!(
+            s,
+            "#[derive(Debug, Clone, Serialize, Deserialize{default})]"
+        )
+        .unwrap();
+        writeln!(s, "pub struct {name} {{").unwrap();
+        for (field, ty) in fields.iter().zip(rust_fields) {
+            write_doc(&mut s, "    ", field.schema["description"].as_str());
+            let ident = field_name(&field.json_name);
+            if ident.trim_start_matches("r#") != field.json_name {
+                writeln!(s, "    #[serde(rename = {:?})]", field.json_name).unwrap();
+            }
+            if field.required {
+                writeln!(s, "    pub {ident}: {ty},").unwrap();
+            } else {
+                writeln!(s, "    #[serde(skip_serializing_if = \"Option::is_none\")]").unwrap();
+                writeln!(s, "    pub {ident}: Option<{ty}>,").unwrap();
+            }
+        }
+        writeln!(s, "}}\n").unwrap();
+        self.out += &s;
+    }
+
+    /// Collects properties of a schema, flattening `allOf` and merging
+    /// object properties that are defined by more than one part.
+    fn collect_fields(&self, schema: &Value, prefix: &str, fields: &mut Vec<Field>) {
+        if let Some(r) = schema["$ref"].as_str() {
+            let name = ref_name(r);
+            self.collect_fields(&self.schemas[name], &type_name(name), fields);
+            return;
+        }
+
+        for part in schema["allOf"].as_array().into_iter().flatten() {
+            self.collect_fields(part, prefix, fields);
+        }
+
+        let required: Vec<&str> = (schema["required"].as_array().into_iter().flatten())
+            .filter_map(Value::as_str)
+            .collect();
+
+        for (json_name, prop) in schema["properties"].as_object().into_iter().flatten() {
+            let field = Field {
+                json_name: json_name.clone(),
+                schema: prop.clone(),
+                required: required.contains(&json_name.as_str()) && prop["nullable"] != true,
+                prefix: prop["x-prefix"].as_str().unwrap_or(prefix).to_string(),
+                merged: false,
+            };
+
+            match fields.iter_mut().find(|f| f.json_name == *json_name) {
+                Some(existing) => merge_field(existing, field),
+                None => fields.push(field),
+            }
+        }
+    }
+
+    fn enum_type(&mut self, schema: &Value, name: &str) -> String {
+        let values = schema["enum"].as_array().unwrap();
+        let is_int = values.iter().all(Value::is_i64);
+
+        let key = format!("{is_int}{values:?}");
+        if let Some(existing) = self.enums.get(&key) {
+            if existing != name && self.emitted.insert(name.to_string()) {
+                writeln!(self.out, "pub type {name} = {existing};\n").unwrap();
+            }
+            return name.to_string();
+        }
+        self.enums.insert(key, name.to_string());
+        if !self.emitted.insert(name.to_string()) {
+            return name.to_string();
+        }
+
+        let mut s = String::new();
+        write_doc(&mut s, "", schema["description"].as_str());
+        let mut used = BTreeSet::new();
+        if is_int {
+            writeln!(
+                s,
+                "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize_repr, Deserialize_repr)]"
+            )
+            .unwrap();
+            writeln!(s, "#[repr(i32)]").unwrap();
+            writeln!(s, "pub enum {name} {{").unwrap();
+            for v in values {
+                let v = v.as_i64().unwrap();
+                let variant = unique(&mut used, variant_name(&v.to_string()));
+                writeln!(s, "    {variant} = {v},").unwrap();
+            }
+        } else {
+            writeln!(
+                s,
+                "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]"
+            )
+            .unwrap();
+            writeln!(s, "pub enum {name} {{").unwrap();
+            for v in values {
+                let v = v.as_str().unwrap();
+                let variant = unique(&mut used, variant_name(v));
+                writeln!(s, "    #[serde(rename = {v:?})]").unwrap();
+                writeln!(s, "    {variant},").unwrap();
+            }
+        }
+        writeln!(s, "}}\n").unwrap();
+        self.out += &s;
+        name.to_string()
+    }
+
+    fn operation(&mut self, path: &str, method: &str, op: &Value) {
+        let Some(op_id) = op["operationId"].as_str() else {
+            return;
+        };
+        let fn_name = snake_case(op_id);
+        let op_type = pascal_case(op_id);
+
+        // parameters
+        let mut path_args = Vec::new();
+        let mut query_args = Vec::new();
+        let mut accept = None;
+        let mut paginated = false;
+        for param in op["parameters"].as_array().into_iter().flatten() {
+            let name = param["name"].as_str().unwrap();
+            match param["in"].as_str() {
+                Some("path") => path_args.push((name.to_string(), field_name(name))),
+                Some("query") if name == "cursor" => paginated = true,
+                Some("query") if name == "limit" => {}
+                Some("query") => {
+                    let ty = match param["schema"]["type"].as_str() {
+                        Some("boolean") => "bool",
+                        Some("integer") => "i64",
+                        _ => "&str",
+                    };
+                    query_args.push((name.to_string(), field_name(name), ty));
+                }
+                Some("header") if name == "Accept" => {
+                    accept = param["schema"]["default"].as_str().map(str::to_string);
+                }
+                _ => {}
+            }
+        }
+
+        // request body
+        let body_schema = op["requestBody"]["content"]
+            .as_object()
+            .and_then(|c| c.values().next())
+            .map(|c| c["schema"].clone());
+        let body_type = body_schema.map(|schema| {
+            let base = schema["$ref"].as_str().map(ref_name);
+            match base {
+                Some(base)
+                    if self.variants.contains_key(base) && !is_opaque(&self.schemas[base]) =>
+                {
+                    let ty = self.component(base);
+                    format!("impl {ty}Like")
+                }
+                _ => self.rust_type(&schema, &format!("{op_type}Request")),
+            }
+        });
+
+        // response
+        let response = op["responses"]
+            .as_object()
+            .and_then(|r| r.iter().find(|(code, _)| code.starts_with('2')))
+            .map(|(_, r)| r["content"]["application/json"]["schema"].clone())
+            .unwrap_or(Value::Null);
+        let data = &response["properties"]["data"];
+        let response = if data.is_null() {
+            ResponseKind::Empty
+        } else if let Some(items) = data["properties"]["items"].as_object() {
+            let item = Value::Object(items.clone());
+            let ty = self.rust_type(&item["items"], &format!("{op_type}Item"));
+            ResponseKind::Page(ty)
+        } else {
+            let base = data["$ref"].as_str().map(ref_name);
+            match base {
+                Some(base)
+                    if self.variants.contains_key(base) && !is_opaque(&self.schemas[base]) =>
+                {
+                    ResponseKind::Variant(self.component(base))
+                }
+                _ => ResponseKind::Data(self.rust_type(data, &format!("{op_type}Response"))),
+            }
+        };
+        let paginated = paginated && matches!(response, ResponseKind::Page(_));
+
+        // signature
+        let mut s = String::new();
+        write_doc(&mut s, "", op["summary"].as_str());
+        if op["description"].is_string() {
+            writeln!(s, "///").unwrap();
+        }
+        write_doc(&mut s, "", op["description"].as_str());
+        writeln!(s, "///\n/// `{} {path}`", method.to_uppercase()).unwrap();
+
+        let mut args = vec![];
+        for (_, arg) in &path_args {
+            args.push(format!("{arg}: &str"));
+        }
+        for (_, arg, ty) in &query_args {
+            args.push(format!("{arg}: Option<{ty}>"));
+        }
+        if let Some(body) = &body_type {
+            args.push(format!("body: &{body}"));
+        }
+
+        let path_fmt = path_args.iter().fold(path.to_string(), |p, (name, arg)| {
+            p.replace(
+                &format!("{{{name}}}"),
+                &format!("{{{}}}", arg.trim_start_matches("r#")),
+            )
+        });
+        let method = format!("reqwest::Method::{}", method.to_uppercase());
+        let accept = accept.map(|a| format!("\n        .header(reqwest::header::ACCEPT, {a:?})"));
+        let accept = accept.as_deref().unwrap_or_default();
+
+        if paginated {
+            let ResponseKind::Page(item) = &response else {
+                unreachable!()
+            };
+            let args = args.join(", ");
+            writeln!(
+                s,
+                "pub fn {fn_name}<'a>(client: &'a Client, {args}) -> impl Stream<Item = Result<{item}, FivetranError>> + 'a {{"
+            )
+            .unwrap();
+            writeln!(s, "    log::info!(\"{fn_name}\");").unwrap();
+            writeln!(s, "    let path = format!(\"{path_fmt}\");").unwrap();
+            for (_, arg, ty) in &query_args {
+                if *ty == "&str" {
+                    writeln!(s, "    let {arg} = {arg}.map(str::to_string);").unwrap();
+                }
+            }
+            writeln!(s, "    list_paginated(move || {{").unwrap();
+            let m = if query_args.is_empty() { "" } else { "mut " };
+            writeln!(
+                s,
+                "        let {m}req = client.request({method}, &path){accept};"
+            )
+            .unwrap();
+            for (name, arg, _) in &query_args {
+                writeln!(
+                    s,
+                    "        if let Some(v) = &{arg} {{ req = req.query(&[({name:?}, v)]); }}"
+                )
+                .unwrap();
+            }
+            writeln!(s, "        req\n    }})\n}}\n").unwrap();
+            self.out += &s;
+            return;
+        }
+
+        let (generics, ret) = match &response {
+            ResponseKind::Empty => (String::new(), "()".to_string()),
+            ResponseKind::Data(ty) => (String::new(), ty.clone()),
+            ResponseKind::Page(item) => (String::new(), format!("super::Page<{item}>")),
+            ResponseKind::Variant(base) => (format!("<R: {base}Like>"), "R".to_string()),
+        };
+        writeln!(
+            s,
+            "pub async fn {fn_name}{generics}(client: &Client, {}) -> Result<{ret}, FivetranError> {{",
+            args.join(", ")
+        )
+        .unwrap();
+        writeln!(s, "    log::info!(\"{fn_name}\");").unwrap();
+        let m = if query_args.is_empty() { "" } else { "mut " };
+        writeln!(
+            s,
+            "    let {m}req = client\n        .request({method}, &format!(\"{path_fmt}\")){accept};"
+        )
+        .unwrap();
+        for (name, arg, _) in &query_args {
+            writeln!(
+                s,
+                "    if let Some(v) = {arg} {{ req = req.query(&[({name:?}, v)]); }}"
+            )
+            .unwrap();
+        }
+        let mut send = String::from("    let res = req");
+        if body_type.is_some() {
+            send += ".json(body)";
+        }
+        if method.ends_with("PATCH") || IDEMPOTENT_POSTS.contains(&op_id) {
+            send += ".idempotent()";
+        }
+        writeln!(s, "{send}.send().await?;").unwrap();
+        match response {
+            ResponseKind::Empty => {
+                writeln!(s, "    receive_api_response_empty(res).await").unwrap()
+            }
+            _ => writeln!(s, "    receive_api_response(res).await").unwrap(),
+        }
+        writeln!(s, "}}\n").unwrap();
+        self.out += &s;
+    }
+}
+
+/// Merges a property into one with the same name from an earlier `allOf`
+/// part. Inline objects are merged property by property; each property keeps
+/// the prefix of the part that defined it, so its inline types get the same
+/// name in every schema that includes the part.
+fn merge_field(existing: &mut Field, new: Field) {
+    let is_inline_object =
+        |f: &Field| f.schema["$ref"].is_null() && f.schema["properties"].is_object();
+    if !is_inline_object(existing) || !is_inline_object(&new) {
+        *existing = new;
+        return;
+    }
+
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for f in [&*existing, &new] {
+        let prefix = format!("{}{}", f.prefix, pascal_case(&f.json_name));
+        for (k, v) in f.schema["properties"].as_object().unwrap() {
+            let mut v = v.clone();
+            if v["x-prefix"].is_null() {
+                v["x-prefix"] = json!(prefix);
+            }
+            properties.insert(k.clone(), v);
+        }
+        required.extend(
+            f.schema["required"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .cloned(),
+        );
+    }
+
+    existing.schema = json!({
+        "type": "object",
+        "description": existing.schema["description"],
+        "properties": properties,
+        "required": required,
+    });
+    existing.required |= new.required;
+    existing.merged = true;
+}
+
+/// If the schema is a map described as an object with a placeholder
+/// property, returns the schema of the map values.
+fn map_placeholder(schema: &Value) -> Option<&Value> {
+    let properties = schema["properties"].as_object()?;
+    if properties.len() != 1 {
+        return None;
+    }
+    let (name, value) = properties.iter().next()?;
+    MAP_PLACEHOLDERS.contains(&name.as_str()).then_some(value)
+}
+
+/// Schema without any structure we could generate a type for.
+fn is_opaque(schema: &Value) -> bool {
+    schema["properties"].as_object().is_none_or(Map::is_empty)
+        && schema.get("allOf").is_none()
+        && schema.get("enum").is_none()
+        && matches!(schema["type"].as_str(), None | Some("object"))
+}
+
+fn ref_name(r: &str) -> &str {
+    r.rsplit('/').next().unwrap()
+}
+
+fn write_doc(out: &mut String, indent: &str, text: Option<&str>) {
+    let Some(text) = text.map(str::trim).filter(|t| !t.is_empty()) else {
+        return;
+    };
+    for line in text.lines() {
+        // this is not rustdoc-flavored markdown, don't let it become a doctest
+        let line = line.replace("```", "'''");
+        writeln!(out, "{indent}/// {}", line.trim_end()).unwrap();
+    }
+}
+
+/// `postgres_config_V1` -> `PostgresConfigV1`
+fn type_name(schema_name: &str) -> String {
+    let name = pascal_case(schema_name);
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("Service{name}")
+    } else {
+        name
+    }
+}
+
+fn pascal_case(s: &str) -> String {
+    s.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+/// `connectionId` -> `connection_id`
+fn snake_case(s: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !out.ends_with('_') {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else if c.is_ascii_alphanumeric() {
+            out.push(c);
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+    }
+    out
+}
+
+fn field_name(json_name: &str) -> String {
+    let name = snake_case(json_name);
+    match name.as_str() {
+        "type" | "ref" | "match" | "move" | "in" | "use" | "mod" | "fn" | "impl" | "where"
+        | "loop" | "async" | "await" | "dyn" | "gen" | "box" | "final" | "static" | "const" => {
+            format!("r#{name}")
+        }
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{name}"),
+        _ => name,
+    }
+}
+
+/// `WAL_PGOUTPUT` -> `WalPgoutput`, `SshTunnel` -> `SshTunnel`, `-3` -> `Minus3`
+fn variant_name(value: &str) -> String {
+    let mut name = String::new();
+    if value.starts_with('-') {
+        name += "Minus";
+    } else if value.starts_with('+') {
+        name += "Plus";
+    }
+    for word in value.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = word.chars();
+        let Some(first) = chars.next() else {
+            continue;
+        };
+        name.push(first.to_ascii_uppercase());
+        if word.chars().any(|c| c.is_ascii_lowercase()) {
+            name.extend(chars);
+        } else {
+            name.extend(chars.map(|c| c.to_ascii_lowercase()));
+        }
+    }
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert_str(0, "Value");
+    }
+    name
+}
+
+fn unique(used: &mut BTreeSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut i = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{name}{i}");
+        i += 1;
+    }
+    candidate
+}
@@ -0,0 +1,5 @@
+-- tables: gel_public.book, gel_public.genre
+SELECT b.title, b.pages, g.name as genre
+FROM ONLY gel_public.book b
+LEFT JOIN gel_public.genre g on (g.id = b.genre_id)
+ORDER BY b.title
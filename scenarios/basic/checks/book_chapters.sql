@@ -0,0 +1,5 @@
+-- tables: gel_public.book, gel_public.book_chapters
+SELECT b.title, bc.target as chapter
+FROM ONLY gel_public.book_chapters bc
+LEFT JOIN gel_public.book b on (b.id = bc.source)
+ORDER BY b.title, bc.target
@@ -0,0 +1,5 @@
+SELECT table_schema, table_name, column_name
+FROM information_schema.columns
+WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+  AND column_name NOT LIKE '_fivetran_%'
+ORDER BY table_schema, table_name, ordinal_position
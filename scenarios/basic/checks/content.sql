@@ -0,0 +1,5 @@
+-- tables: gel_public.content, gel_public.genre
+SELECT c.title, g.name as genre
+FROM ONLY gel_public.content c
+LEFT JOIN gel_public.genre g on (g.id = c.genre_id)
+ORDER BY c.title
@@ -0,0 +1,4 @@
+SELECT b.title, p.name as publisher
+FROM {prefix}_public.book b
+JOIN {prefix}_public.publisher p on (p.id = b.publisher_id)
+ORDER BY b.title
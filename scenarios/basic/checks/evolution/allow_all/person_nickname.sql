@@ -0,0 +1,3 @@
+SELECT first_name, nickname FROM {prefix}_public.person
+WHERE nickname IS NOT NULL
+ORDER BY first_name
@@ -0,0 +1,3 @@
+SELECT table_name FROM information_schema.tables
+WHERE table_schema = '{prefix}_public' AND table_name LIKE 'p%'
+ORDER BY table_name
@@ -0,0 +1 @@
+SELECT title, release_year FROM {prefix}_public.movie ORDER BY title
@@ -0,0 +1,4 @@
+SELECT column_name FROM information_schema.columns
+WHERE table_schema = '{prefix}_public' AND table_name = 'person'
+  AND column_name NOT LIKE '_fivetran_%'
+ORDER BY ordinal_position
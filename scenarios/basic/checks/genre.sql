@@ -0,0 +1,2 @@
+-- tables: gel_public.genre
+SELECT name FROM gel_public.genre ORDER BY name
@@ -0,0 +1,2 @@
+-- tables: gel_public___nested.hello
+SELECT hello FROM gel_public___nested.hello ORDER BY hello
@@ -0,0 +1,6 @@
+-- tables: gel_public.genre, gel_public.movie, gel_public.person
+SELECT title, release_year, d.first_name as director, g.name as genre
+FROM gel_public.movie m
+LEFT JOIN gel_public.genre g on (g.id = m.genre_id)
+LEFT JOIN gel_public.person d on (d.id = m.director_id)
+ORDER BY title
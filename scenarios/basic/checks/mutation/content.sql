@@ -0,0 +1,4 @@
+-- tables: gel_public.content
+SELECT c.title, c._fivetran_deleted
+FROM gel_public.content c
+ORDER BY c.title
@@ -0,0 +1,6 @@
+-- tables: gel_public.movie, gel_public.movie_actors, gel_public.person
+SELECT m.title, a.first_name, ma._fivetran_deleted
+FROM gel_public.movie_actors ma
+LEFT JOIN gel_public.movie m on (m.id = ma.source)
+LEFT JOIN gel_public.person a on (a.id = ma.target)
+ORDER BY m.title, a.first_name
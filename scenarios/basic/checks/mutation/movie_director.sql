@@ -0,0 +1,5 @@
+-- tables: gel_public.movie, gel_public.person
+SELECT m.title, d.first_name as director, m._fivetran_deleted
+FROM gel_public.movie m
+LEFT JOIN gel_public.person d on (d.id = m.director_id)
+ORDER BY m.title
@@ -0,0 +1,4 @@
+-- tables: gel_public.novel_chapters
+SELECT nc.target as chapter, nc._fivetran_deleted
+FROM gel_public.novel_chapters nc
+ORDER BY nc.target
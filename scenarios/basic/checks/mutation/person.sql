@@ -0,0 +1,4 @@
+-- tables: gel_public.person
+SELECT first_name, last_name, _fivetran_deleted
+FROM gel_public.person
+ORDER BY first_name
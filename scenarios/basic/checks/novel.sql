@@ -0,0 +1,5 @@
+-- tables: gel_public.genre, gel_public.novel
+SELECT n.title, n.pages, g.name as genre
+FROM ONLY gel_public.novel n
+LEFT JOIN gel_public.genre g on (g.id = n.genre_id)
+ORDER BY n.title
@@ -0,0 +1,5 @@
+-- tables: gel_public.novel, gel_public.novel_chapters
+SELECT n.title, nc.target as chapter
+FROM ONLY gel_public.novel_chapters nc
+LEFT JOIN gel_public.novel n on (n.id = nc.source)
+ORDER BY n.title, nc.target
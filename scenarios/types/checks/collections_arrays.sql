@@ -0,0 +1,4 @@
+-- tables: gel_public.collections
+SELECT label, p_array_int64, p_array_str, p_tuple, p_named_tuple
+FROM gel_public.collections
+ORDER BY label
@@ -0,0 +1,5 @@
+-- tables: gel_public.collections
+SELECT label, p_range_int64::text, p_range_datetime::text, p_multirange_int64::text,
+       p_vector::text
+FROM gel_public.collections
+ORDER BY label
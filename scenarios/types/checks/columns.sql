@@ -0,0 +1,5 @@
+SELECT table_name, column_name, data_type
+FROM information_schema.columns
+WHERE table_schema = 'gel_public'
+  AND column_name NOT LIKE '_fivetran_%'
+ORDER BY table_name, column_name
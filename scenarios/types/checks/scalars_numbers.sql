@@ -0,0 +1,4 @@
+-- tables: gel_public.scalars
+SELECT label, p_bool, p_int16, p_int32, p_int64, p_float32, p_float64, p_bigint, p_decimal
+FROM gel_public.scalars
+ORDER BY label
@@ -0,0 +1,4 @@
+-- tables: gel_public.scalars
+SELECT label, p_str, p_uuid, p_bytes, p_json, p_enum
+FROM gel_public.scalars
+ORDER BY label
@@ -0,0 +1,5 @@
+-- tables: gel_public.scalars
+SELECT label, p_datetime, p_duration, p_local_date, p_local_time, p_local_datetime,
+       p_relative_duration, p_date_duration
+FROM gel_public.scalars
+ORDER BY label
@@ -0,0 +1,4 @@
+-- tables: gel_public.special
+SELECT label, p_float32, p_float64
+FROM gel_public.special
+ORDER BY label
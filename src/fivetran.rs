@@ -1,107 +1,647 @@
-#![allow(dead_code)]
-
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-use chrono::{Datelike, Timelike, Utc};
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use futures::{Stream, TryStreamExt, stream};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use serde_repr::{Deserialize_repr, Serialize_repr};
 
+mod alerts;
+mod api;
+pub mod cassette;
+mod cleanup;
+mod config;
+pub mod mock;
+mod registry;
+mod retry;
+mod setup_tests;
+mod state;
+
+pub use alerts::SyncFailed;
+pub use cassette::Cassette;
+pub use cleanup::{CleanupOld, cleanup_old};
+pub use config::{Auth, ClientConfig, ClientOptions};
+pub use registry::Registry;
+pub use retry::RetryPolicy;
+pub use state::ConnectionState;
+
+use crate::report::Report;
+
+/// Sets up Fivetran objects and runs the first sync.
 pub async fn setup_sync(
-    pg_addr: SocketAddr,
-    gel_addr: SocketAddr,
+    client: &Client,
+    registry: &Registry,
+    report: &Report,
+    pg_addr: &Endpoint,
+    source: &Source<'_>,
 ) -> anyhow::Result<CreatedObjects> {
-    let client = Client::new();
+    let objects = setup(client, registry, report, pg_addr, source).await?;
+    report
+        .phase("first sync", async {
+            report.object(format!("connection {}", objects.connection_id));
+            first_sync(client, &objects).await
+        })
+        .await?;
+    Ok(objects)
+}
 
-    let group = create_group(&client).await?;
-    let destination = create_destination(&client, &group.id, pg_addr).await?;
-    log::debug!("destination = {destination:#?}");
+/// Creates a group with a destination and a connection and configures the
+/// connection's schema, without syncing anything. Each step is a phase of
+/// the report.
+///
+/// Each object goes into the `registry` as soon as it exists.
+pub async fn setup(
+    client: &Client,
+    registry: &Registry,
+    report: &Report,
+    pg_addr: &Endpoint,
+    source: &Source<'_>,
+) -> anyhow::Result<CreatedObjects> {
+    let created = |resource: registry::Resource| {
+        report.object(&resource);
+        registry.add(resource);
+    };
 
-    let mut connector = create_connector(&client, &group.id, gel_addr).await?;
-    log::debug!("connector = {connector:#?}");
-    while connector.status.setup_state != "connected" {
-        log::info!("waiting for connector to have `setup_state` == \"connected\"");
-        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+    let group = report
+        .infrastructure("create group", async {
+            let group = create_group(client).await?;
+            created(registry::Resource::Group(group.id.clone()));
+            if let Some(tunnel) = pg_addr.ssh_tunnel().or(source.endpoint.ssh_tunnel()) {
+                let key = api::group_ssh_public_key(client, &group.id).await?;
+                log::info!(
+                    "{tunnel} has to accept the SSH key of group {}: {}",
+                    group.id,
+                    key.public_key
+                );
+            }
+            Ok(group)
+        })
+        .await?;
 
-        connector = get_connector(&client, &connector.id).await?;
-        log::debug!("connector.status = {:#?}", connector.status);
-    }
+    let destination = report
+        .infrastructure("create destination", async {
+            let destination = create_destination(client, &group.id, pg_addr).await?;
+            created(registry::Resource::Destination(destination.id.clone()));
+            log::debug!("destination = {destination:#?}");
+            setup_tests::check(
+                &format!("destination {}", destination.id),
+                destination.setup_tests.as_deref(),
+                report,
+            )?;
+            Ok(destination)
+        })
+        .await?;
 
-    let schema = reload_connector_schema_config(&client, &connector.id).await?;
-    log::trace!("schema = {schema:#?}");
+    let connector = report
+        .phase("create connection", async {
+            let connector = create_connector(client, &group.id, source).await?;
+            created(registry::Resource::Connection(connector.id.clone()));
+            log::debug!("connector = {connector:#?}");
+            Ok(connector)
+        })
+        .await?;
+    let connector_name = format!("connection {}", connector.id);
+
+    let connector = report
+        .phase("connection setup", async {
+            report.object(&connector_name);
+            let mut connector = connector;
+            let started = Instant::now();
+            while connector.status.setup_state != "connected" {
+                // tests can fail before setup is complete, no need to wait for that
+                setup_tests::check_failed(&connector_name, connector.setup_tests.as_deref())?;
+                if connector.status.setup_state == "broken" {
+                    anyhow::bail!("{connector_name}: setup is broken");
+                }
+
+                log::info!("waiting for connector to have `setup_state` == \"connected\"");
+                client.poll_wait(started, "connection setup").await?;
+
+                connector = get_connector(client, &connector.id).await?;
+                log::debug!("connector.status = {:#?}", connector.status);
+            }
+            setup_tests::check(&connector_name, connector.setup_tests.as_deref(), report)?;
+            Ok(connector)
+        })
+        .await?;
 
-    update_connector_schema_config(
-        &client,
-        &connector.id,
-        &UpdateConnectorSchemaRequest {
-            schema_change_handling: SchemaChangeHandling::BlockAll,
-            schemas: pick_schema(schema),
-        },
-    )
-    .await?;
+    report
+        .phase("schema reload", async {
+            report.object(&connector_name);
+            let schema = api::reload_connection_schema_config(
+                client,
+                &connector.id,
+                &api::ReloadStandardConfigRequest::default(),
+            )
+            .await?;
+            log::trace!("schema = {schema:#?}");
+
+            api::modify_connection_schema_config(
+                client,
+                &connector.id,
+                &api::StandardConfigUpdateRequest {
+                    schema_change_handling: Some(source.schema_change_handling.api()),
+                    schemas: pick_schema(schema, source.skip),
+                },
+            )
+            .await?;
+            Ok(())
+        })
+        .await?;
+
+    Ok(CreatedObjects {
+        group,
+        destination,
+        connection_id: connector.id,
+    })
+}
 
-    let mut connector = start_sync(&client, &connector.id).await?;
+/// Un-pauses the connection set up by [setup] and waits for its first sync.
+/// Fails with [SyncFailed] if the sync fails.
+pub async fn first_sync(client: &Client, objects: &CreatedObjects) -> anyhow::Result<()> {
+    let connector = start_sync(client, &objects.connection_id).await?;
     log::debug!("connector.status = {:#?}", connector.status);
-    while connector.failed_at.is_none() && connector.succeeded_at.is_none() {
+    let connector = wait_for_sync(client, connector).await?;
+
+    log::debug!("connector = {:#?}", connector);
+    log::info!("succeeded");
+    Ok(())
+}
+
+/// Waits until a sync finishes, that is, until `succeeded_at` or `failed_at`
+/// changes from what they were in `connector`.
+///
+/// Returns the connection if the sync succeeded, after logging its tasks and
+/// warnings. Fails with [SyncFailed], which carries them, if it failed.
+async fn wait_for_sync(
+    client: &Client,
+    connector: api::ConnectorResponseV1,
+) -> anyhow::Result<api::ConnectorResponseV1> {
+    let started = Instant::now();
+    let succeeded_at = connector.succeeded_at.clone();
+    let failed_at = connector.failed_at.clone();
+
+    let mut connector = connector;
+    loop {
+        if connector.succeeded_at != succeeded_at {
+            alerts::log(&connector.id, &connector.status);
+            return Ok(connector);
+        }
+        if connector.failed_at != failed_at {
+            return Err(SyncFailed::new(connector).into());
+        }
+
         log::info!("waiting for connector sync to succeed or fail");
-        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+        client.poll_wait(started, "sync").await?;
 
-        connector = get_connector(&client, &connector.id).await?;
+        connector = get_connector(client, &connector.id).await?;
         log::debug!("connector.status = {:#?}", connector.status);
     }
+}
 
-    log::debug!("connector = {:#?}", connector);
+/// How Fivetran reaches a database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Direct(SocketAddr),
+    /// Through an SSH host, to `addr` as seen from that host.
+    SshTunnel {
+        tunnel: SshTunnel,
+        addr: SocketAddr,
+    },
+}
+
+impl Endpoint {
+    fn addr(&self) -> SocketAddr {
+        match self {
+            Endpoint::Direct(addr) | Endpoint::SshTunnel { addr, .. } => *addr,
+        }
+    }
+
+    fn ssh_tunnel(&self) -> Option<&SshTunnel> {
+        match self {
+            Endpoint::Direct(_) => None,
+            Endpoint::SshTunnel { tunnel, .. } => Some(tunnel),
+        }
+    }
+
+    fn connection_type(&self) -> api::PostgresConfigV1ConfigConnectionType {
+        match self {
+            Endpoint::Direct(_) => api::PostgresConfigV1ConfigConnectionType::Directly,
+            Endpoint::SshTunnel { .. } => api::PostgresConfigV1ConfigConnectionType::SshTunnel,
+        }
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Direct(addr) => write!(f, "{addr}"),
+            Endpoint::SshTunnel { tunnel, addr } => write!(f, "{addr} via {tunnel}"),
+        }
+    }
+}
+
+/// SSH host that Fivetran connects through, as `user@host[:port]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshTunnel {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+}
+
+impl std::str::FromStr for SshTunnel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((user, host)) = s.split_once('@') else {
+            anyhow::bail!("expected `user@host[:port]`, found `{s}`");
+        };
+        let (host, port) = match host.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse()?),
+            None => (host, 22),
+        };
+        Ok(SshTunnel {
+            host: host.to_string(),
+            port,
+            user: user.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for SshTunnel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}:{}", self.user, self.host, self.port)
+    }
+}
+
+/// The Gel database that a connection syncs from, and how.
+pub struct Source<'a> {
+    pub endpoint: &'a Endpoint,
+    /// Postgres database of the Gel branch.
+    pub database: &'a str,
+    /// Parts of the schema that are not synced.
+    pub skip: &'a [Skip],
+    pub update_method: UpdateMethod,
+    /// Prefix of the destination schemas, e.g. `gel` for `gel_public`.
+    pub schema_prefix: &'a str,
+    pub schema_change_handling: SchemaChangeHandling,
+}
+
+/// What the connection does with schemas, tables and columns that appear in
+/// Gel after setup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemaChangeHandling {
+    /// Syncs them
+    AllowAll,
+    /// Syncs new columns, but not new schemas and tables
+    AllowColumns,
+    /// Syncs none of them
+    BlockAll,
+}
 
-    if connector.failed_at.is_some() {
-        log::error!("failed")
+impl SchemaChangeHandling {
+    fn api(self) -> api::StandardConfigResponseSchemaChangeHandling {
+        match self {
+            SchemaChangeHandling::AllowAll => {
+                api::StandardConfigResponseSchemaChangeHandling::AllowAll
+            }
+            SchemaChangeHandling::AllowColumns => {
+                api::StandardConfigResponseSchemaChangeHandling::AllowColumns
+            }
+            SchemaChangeHandling::BlockAll => {
+                api::StandardConfigResponseSchemaChangeHandling::BlockAll
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for SchemaChangeHandling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SchemaChangeHandling::AllowAll => "allow_all",
+            SchemaChangeHandling::AllowColumns => "allow_columns",
+            SchemaChangeHandling::BlockAll => "block_all",
+        })
+    }
+}
+
+/// How the connection detects changed rows in Gel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum UpdateMethod {
+    /// Reads rows whose xmin system column changed
+    Xmin,
+    /// Tails the WAL through a logical replication slot with test_decoding
+    Wal,
+    /// Tails the WAL through a logical replication slot with pgoutput
+    WalPgoutput,
+    /// Compares compressed snapshots of tables
+    Teleport,
+}
+
+/// Logical replication slot that the connection reads, for WAL methods.
+pub const REPLICATION_SLOT: &str = "fivetran_slot";
+
+/// Publication of all tables, for [UpdateMethod::WalPgoutput].
+pub const PUBLICATION: &str = "fivetran_publication";
+
+impl UpdateMethod {
+    /// The Gel SQL adapter has no logical replication, and Teleport relies
+    /// on Postgres functions that it does not expose.
+    pub fn expect_unsupported(self) -> bool {
+        self != UpdateMethod::Xmin
+    }
+
+    /// Xmin only reads rows that still exist, so rows deleted in Gel stay in
+    /// the destination without being marked as deleted.
+    pub fn syncs_deletes(self) -> bool {
+        self != UpdateMethod::Xmin
+    }
+
+    /// Output plugin of the replication slot, if the method needs one.
+    pub fn replication_plugin(self) -> Option<&'static str> {
+        match self {
+            UpdateMethod::Wal => Some("test_decoding"),
+            UpdateMethod::WalPgoutput => Some("pgoutput"),
+            UpdateMethod::Xmin | UpdateMethod::Teleport => None,
+        }
+    }
+
+    fn api(self) -> api::PostgresConfigV1ConfigUpdateMethod {
+        match self {
+            UpdateMethod::Xmin => api::PostgresConfigV1ConfigUpdateMethod::Xmin,
+            UpdateMethod::Wal => api::PostgresConfigV1ConfigUpdateMethod::Wal,
+            UpdateMethod::WalPgoutput => api::PostgresConfigV1ConfigUpdateMethod::WalPgoutput,
+            UpdateMethod::Teleport => api::PostgresConfigV1ConfigUpdateMethod::Teleport,
+        }
+    }
+}
+
+impl std::fmt::Display for UpdateMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+
+        let value = self.to_possible_value().unwrap();
+        f.write_str(value.get_name())
+    }
+}
+
+/// A schema, table or column of the source that is not synced. Without
+/// `table`, the whole schema is skipped; without `column`, the whole table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Skip {
+    pub schema: String,
+    pub table: Option<String>,
+    pub column: Option<String>,
+}
+
+impl Skip {
+    fn matches(&self, schema: &str, table: Option<&str>, column: Option<&str>) -> bool {
+        self.schema == schema
+            && (self.table.is_none() || self.table.as_deref() == table)
+            && (self.column.is_none() || self.column.as_deref() == column)
+    }
+}
+
+/// A table in the source database, as `schema.Table`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceTable {
+    pub schema: String,
+    pub table: String,
+}
+
+impl std::str::FromStr for SourceTable {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((schema, table)) = s.split_once('.') else {
+            anyhow::bail!("expected `schema.Table`, found `{s}`");
+        };
+        Ok(SourceTable {
+            schema: schema.to_string(),
+            table: table.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for SourceTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.schema, self.table)
+    }
+}
+
+/// Re-syncs some tables of the created connection and waits for the sync.
+///
+/// Returns names of the destination tables, as `schema.table`.
+pub async fn resync_and_wait(
+    client: &Client,
+    objects: &CreatedObjects,
+    tables: &[SourceTable],
+) -> anyhow::Result<Vec<String>> {
+    let connection_id = &objects.connection_id;
+
+    let config = api::connection_schema_config(client, connection_id).await?;
+    let destination_tables = tables
+        .iter()
+        .map(|t| {
+            let schema = config.schemas.get(&t.schema);
+            let table = schema.and_then(|s| s.tables.get(&t.table));
+            let (Some(schema), Some(table)) = (schema, table) else {
+                anyhow::bail!("table {t} is not in the schema config of the connection");
+            };
+            Ok(format!(
+                "{}.{}",
+                schema.name_in_destination, table.name_in_destination
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let connector = get_connector(client, connection_id).await?;
+    resync_tables(client, connection_id, tables).await?;
+    wait_for_sync(client, connector)
+        .await
+        .with_context(|| format!("re-sync of {tables:?}"))?;
+
+    Ok(destination_tables)
+}
+
+/// Re-syncs all tables of the created connection from scratch and waits for
+/// the sync.
+pub async fn resync_connection_and_wait(
+    client: &Client,
+    objects: &CreatedObjects,
+) -> anyhow::Result<()> {
+    let connector = get_connector(client, &objects.connection_id).await?;
+    resync(client, &objects.connection_id).await?;
+    wait_for_sync(client, connector)
+        .await
+        .context("re-sync of the connection")?;
+    Ok(())
+}
+
+/// Triggers a sync of the created connection and waits for it to succeed.
+/// Un-pauses the connection if needed.
+pub async fn sync_and_wait(client: &Client, objects: &CreatedObjects) -> anyhow::Result<()> {
+    let mut connector = get_connector(client, &objects.connection_id).await?;
+    if connector.paused {
+        connector = start_sync(client, &objects.connection_id).await?;
     } else {
-        log::info!("succeeded");
+        sync(client, &objects.connection_id, false).await?;
     }
-    Ok(CreatedObjects { group, destination })
+    wait_for_sync(client, connector).await?;
+    Ok(())
 }
 
-/// Picks schema objects that we want to sync.
-fn pick_schema(schema: StandardConfigResponse) -> HashMap<String, UpdateConnectorSchema> {
-    const SKIP_COLUMNS: &[(&str, &str, &str)] = &[
-        // Don't sync username, because it is a computed that needs a global,
-        // and we don't support globals over COPY yet.
-        ("public", "Person", "username"),
-    ];
+/// Reads the state of the created connection and logs its xmin cursor.
+///
+/// Returns `None` if Fivetran does not expose the state of the connection.
+pub async fn record_state(
+    client: &Client,
+    objects: &CreatedObjects,
+) -> anyhow::Result<Option<ConnectionState>> {
+    let Some(state) = state::get(client, &objects.connection_id).await? else {
+        log::info!("connection state is not available");
+        return Ok(None);
+    };
+    log::info!("xmin cursor: {:?}", state.xmin_cursor());
+    log::debug!("connection state = {:#}", state.0);
+    Ok(Some(state))
+}
+
+/// Puts a previously recorded state back and syncs, so the connector reads
+/// again everything that changed after the state was recorded.
+pub async fn rewind_state(
+    client: &Client,
+    objects: &CreatedObjects,
+    state: &ConnectionState,
+) -> anyhow::Result<()> {
+    log::info!("rewinding xmin cursor to {:?}", state.xmin_cursor());
+
+    // state can only be changed while the connection is paused
+    pause(client, &objects.connection_id).await?;
+    state::set(client, &objects.connection_id, state).await?;
+    let connector = start_sync(client, &objects.connection_id).await?;
+    wait_for_sync(client, connector)
+        .await
+        .context("sync after rewinding the connection state")?;
+    Ok(())
+}
+
+/// Schemas, tables and columns in the schema config of a connection, as
+/// `schema`, `schema.Table` and `schema.Table.column`, and whether they are
+/// enabled.
+#[derive(Debug, Default)]
+pub struct SchemaObjects(BTreeMap<String, bool>);
+
+impl SchemaObjects {
+    fn new(config: &api::StandardConfigResponse) -> Self {
+        let mut objects = BTreeMap::new();
+        for (s_name, s) in &config.schemas {
+            objects.insert(s_name.clone(), s.enabled);
+            for (t_name, t) in &s.tables {
+                objects.insert(format!("{s_name}.{t_name}"), t.enabled);
+                for (c_name, c) in &t.columns {
+                    objects.insert(format!("{s_name}.{t_name}.{c_name}"), c.enabled);
+                }
+            }
+        }
+        SchemaObjects(objects)
+    }
+
+    /// Objects that are not in `before`, one line each, like
+    /// `public.Person.nickname: enabled`. Tables of new schemas and columns
+    /// of new tables are left out.
+    pub fn new_since(&self, before: &SchemaObjects) -> String {
+        let is_new = |name: &str| !before.0.contains_key(name);
+        let mut r = String::new();
+        for (name, enabled) in &self.0 {
+            if !is_new(name) {
+                continue;
+            }
+            if let Some((parent, _)) = name.rsplit_once('.')
+                && is_new(parent)
+            {
+                continue;
+            }
+            let enabled = if *enabled { "enabled" } else { "disabled" };
+            r += &format!("{name}: {enabled}\n");
+        }
+        r
+    }
+}
+
+/// Reads the schema config of the created connection.
+pub async fn schema_objects(
+    client: &Client,
+    objects: &CreatedObjects,
+) -> anyhow::Result<SchemaObjects> {
+    let config = api::connection_schema_config(client, &objects.connection_id).await?;
+    Ok(SchemaObjects::new(&config))
+}
+
+/// Reloads the schema config of the created connection, so it includes
+/// changes of the source schema.
+pub async fn reload_schema_objects(
+    client: &Client,
+    objects: &CreatedObjects,
+) -> anyhow::Result<SchemaObjects> {
+    let config = api::reload_connection_schema_config(
+        client,
+        &objects.connection_id,
+        &api::ReloadStandardConfigRequest::default(),
+    )
+    .await?;
+    log::debug!("reloaded schema = {config:#?}");
+    Ok(SchemaObjects::new(&config))
+}
+
+/// Picks schema objects that we want to sync: everything but what `skip`
+/// matches.
+fn pick_schema(
+    schema: api::StandardConfigResponse,
+    skip: &[Skip],
+) -> HashMap<String, api::SchemaUpdateRequest> {
+    let skipped =
+        |s: &str, t: Option<&str>, c: Option<&str>| skip.iter().any(|x| x.matches(s, t, c));
 
     schema
         .schemas
         .into_iter()
         .map(|(s_name, s)| {
             let s_name_ref = s_name.as_str();
-            let s = UpdateConnectorSchema {
-                enabled: true,
-                tables: s
-                    .tables
-                    .into_iter()
-                    .map(|(t_name, t)| {
-                        let t_name_ref = t_name.as_str();
-                        let t = UpdateConnectorTable {
-                            enabled: true,
-                            columns: t
-                                .columns
-                                .into_iter()
-                                .map(|(c_name, c)| {
-                                    let enabled = c.enabled
-                                        && !SKIP_COLUMNS
-                                            .contains(&(s_name_ref, t_name_ref, &c_name));
-                                    let c = UpdateConnectorColumn {
-                                        enabled,
-                                        hashed: Some(false),
-                                        is_primary_key: c.is_primary_key,
-                                    };
-                                    (c_name, c)
-                                })
-                                .collect(),
-                        };
-                        (t_name, t)
-                    })
-                    .collect(),
+            let s = api::SchemaUpdateRequest {
+                enabled: !skipped(s_name_ref, None, None),
+                tables: Some(
+                    s.tables
+                        .into_iter()
+                        .map(|(t_name, t)| {
+                            let t_name_ref = Some(t_name.as_str());
+                            let t = api::TableUpdateRequest {
+                                enabled: !skipped(s_name_ref, t_name_ref, None),
+                                sync_mode: None,
+                                columns: Some(
+                                    t.columns
+                                        .into_iter()
+                                        .map(|(c_name, c)| {
+                                            let enabled = c.enabled
+                                                && !skipped(s_name_ref, t_name_ref, Some(&c_name));
+                                            let c = api::ColumnUpdateRequest {
+                                                enabled,
+                                                hashed: Some(false),
+                                                is_primary_key: c.is_primary_key,
+                                                masking_algorithm: None,
+                                            };
+                                            (c_name, c)
+                                        })
+                                        .collect(),
+                                ),
+                            };
+                            (t_name, t)
+                        })
+                        .collect(),
+                ),
             };
             (s_name, s)
         })
@@ -109,118 +649,338 @@ fn pick_schema(schema: StandardConfigResponse) -> HashMap<String, UpdateConnecto
 }
 
 pub struct CreatedObjects {
-    group: GroupResponse,
-    destination: DestinationExtendedResponse,
+    group: api::GroupResponse,
+    destination: api::DestinationExtendedResponse,
+    connection_id: String,
 }
 
-pub async fn cleanup(objects: &CreatedObjects) -> anyhow::Result<()> {
-    log::info!("cleaning up");
-
-    let client = Client::new();
-
-    let connectors = list_connectors_of_group(&client, &objects.group.id).await?;
-    for connector in &connectors.items {
-        delete_connector(&client, &connector.id).await?;
+impl CreatedObjects {
+    /// Looks up objects created by an earlier [setup], by id of their group.
+    pub async fn find(client: &Client, group_id: &str) -> anyhow::Result<Self> {
+        let group = api::group_details(client, group_id).await?;
+        // destinations have the id of their group
+        let destination: api::DestinationExtendedResponse =
+            api::destination_details(client, group_id).await?;
+        let connectors: Vec<_> = api::list_all_connections_in_group(client, group_id, None)
+            .try_collect()
+            .await?;
+        let [connector] = connectors.as_slice() else {
+            anyhow::bail!(
+                "expected one connection in group {group_id}, found {}",
+                connectors.len()
+            );
+        };
+        Ok(CreatedObjects {
+            group,
+            destination,
+            connection_id: connector.id.clone(),
+        })
     }
-    delete_destination(&client, &objects.destination.id).await?;
-    delete_group(&client, &objects.group.id).await?;
 
-    Ok(())
-}
+    pub fn group_id(&self) -> &str {
+        &self.group.id
+    }
 
-pub async fn cleanup_old() -> anyhow::Result<()> {
-    let client = Client::new();
+    pub fn destination_id(&self) -> &str {
+        &self.destination.id
+    }
 
-    log::info!("removing old connectors");
-    let connectors = list_connectors(&client).await?;
-    for connector in &connectors.items {
-        if is_old(&connector.created_at) {
-            delete_connector(&client, &connector.id).await?;
-        }
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
     }
+}
 
-    log::info!("removing old groups & destinations");
-    let destinations = list_destinations(&client).await?;
-    for destination in destinations.items {
-        let group = get_group(&client, &destination.group_id).await?;
+pub async fn cleanup(client: &Client, objects: &CreatedObjects) -> anyhow::Result<()> {
+    log::info!("cleaning up");
 
-        if is_old(&group.created_at) {
-            delete_destination(&client, &destination.id).await?;
-            delete_group(&client, &destination.group_id).await?;
-        }
+    let connectors: Vec<_> = api::list_all_connections_in_group(client, &objects.group.id, None)
+        .try_collect()
+        .await?;
+    for connector in &connectors {
+        ignore_not_found(api::delete_connection(client, &connector.id).await)?;
     }
+    ignore_not_found(api::delete_destination(client, &objects.destination.id).await)?;
+    ignore_not_found(api::delete_group(client, &objects.group.id).await)?;
 
     Ok(())
 }
 
-fn is_old(created_at: &str) -> bool {
-    let Ok(created_at) = chrono::DateTime::parse_from_str(created_at, "%+") else {
-        return true;
-    };
-    let since_created = Utc::now().signed_duration_since(created_at);
-
-    since_created.num_minutes() > 15
+/// Deleting something that is already gone is not a failure.
+fn ignore_not_found(res: Result<(), FivetranError>) -> Result<(), FivetranError> {
+    match res {
+        Err(e) if e.is_not_found() => {
+            log::info!("  already deleted");
+            Ok(())
+        }
+        r => r,
+    }
 }
 
-struct Client {
+pub struct Client {
     base_url: reqwest::Url,
     inner: reqwest::Client,
+    retry: RetryPolicy,
+    /// How long to wait between checks of a connection's status.
+    poll_interval: Duration,
+    /// How long to wait for a connection setup or a sync. `None` waits forever.
+    wait_timeout: Option<Duration>,
+    authorization: reqwest::header::HeaderValue,
+    cassette: Option<Cassette>,
+    /// Start of the names of created groups.
+    group_prefix: String,
 }
 
 impl Client {
-    fn new() -> Self {
+    pub fn from_config(config: &ClientConfig) -> anyhow::Result<Self> {
+        // set on each request rather than as a default header, so recorded
+        // cassettes see it and can redact it
+        let mut authorization = reqwest::header::HeaderValue::from_str(&config.auth.header_value())
+            .map_err(|_| anyhow::anyhow!("Fivetran credentials contain invalid characters"))?;
+        authorization.set_sensitive(true);
+
         let mut headers = reqwest::header::HeaderMap::new();
-        headers.append(
-            "Authorization",
-            reqwest::header::HeaderValue::from_str(&env::var("FIVETRAN_AUTHORIZATION").unwrap())
-                .unwrap(),
+        headers.insert(
+            reqwest::header::ACCEPT,
+            reqwest::header::HeaderValue::from_str(&config.accept)?,
         );
 
         let inner = reqwest::ClientBuilder::new()
             .default_headers(headers)
-            .build()
-            .unwrap();
-        Client {
+            .user_agent(&config.user_agent)
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+        // paths of endpoints are joined to it, so they must not replace its
+        // last segment
+        let mut base_url = config.base_url.clone();
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+        Ok(Client {
+            inner,
+            base_url,
+            retry: config.retry.clone(),
+            poll_interval: Duration::from_secs(10),
+            wait_timeout: None,
+            authorization,
+            cassette: None,
+            group_prefix: config.group_prefix.clone(),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn with_wait_timeout(mut self, wait_timeout: Option<Duration>) -> Self {
+        self.wait_timeout = wait_timeout;
+        self
+    }
+
+    /// Sleeps before the next check of a connection's status, or fails if
+    /// waiting for `what` started longer than the wait timeout ago.
+    async fn poll_wait(&self, started: Instant, what: &str) -> anyhow::Result<()> {
+        if let Some(timeout) = self.wait_timeout {
+            anyhow::ensure!(
+                started.elapsed() < timeout,
+                "timed out after {timeout:?} waiting for {what}"
+            );
+        }
+        tokio::time::sleep(self.poll_interval).await;
+        Ok(())
+    }
+
+    /// Records all traffic to the cassette, or serves responses from it.
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    pub fn cassette(&self) -> Option<&Cassette> {
+        self.cassette.as_ref()
+    }
+
+    /// Current time, or the time of recording when replaying a cassette.
+    fn now(&self) -> DateTime<Utc> {
+        match &self.cassette {
+            Some(c) if c.is_replay() => c.recorded_at(),
+            _ => Utc::now(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> Request<'_> {
+        let inner = self.url(path).map(|url| {
+            self.inner
+                .request(method.clone(), url)
+                .header(reqwest::header::AUTHORIZATION, self.authorization.clone())
+        });
+        Request {
+            client: self,
+            method,
+            path: path.to_string(),
             inner,
-            base_url: reqwest::Url::parse("https://api.fivetran.com").unwrap(),
+            idempotent: false,
+        }
+    }
+
+    /// URL of an endpoint below the base URL, also if that has a path.
+    fn url(&self, path: &str) -> Result<reqwest::Url, FivetranError> {
+        self.base_url
+            .join(path.trim_start_matches('/'))
+            .map_err(|e| FivetranError::Url {
+                path: path.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    async fn execute(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, FivetranError> {
+        let request = request.build()?;
+        match &self.cassette {
+            Some(cassette) => cassette.execute(&self.inner, request).await,
+            None => Ok(self.inner.execute(request).await?),
         }
     }
+}
+
+/// A request to the Fivetran API that is retried according to the
+/// [RetryPolicy] of its [Client].
+struct Request<'a> {
+    client: &'a Client,
+    method: reqwest::Method,
+    path: String,
+    /// Fails with the URL if the path does not make one.
+    inner: Result<reqwest::RequestBuilder, FivetranError>,
+    idempotent: bool,
+}
 
-    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
-        self.inner
-            .request(method, self.base_url.join(path).unwrap())
+impl Request<'_> {
+    fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        self.inner = self.inner.map(|r| r.json(body));
+        self
+    }
+
+    fn header(mut self, name: reqwest::header::HeaderName, value: &str) -> Self {
+        self.inner = self.inner.map(|r| r.header(name, value));
+        self
+    }
+
+    fn query<T: Serialize + ?Sized>(mut self, query: &T) -> Self {
+        self.inner = self.inner.map(|r| r.query(query));
+        self
+    }
+
+    /// Marks a POST or PATCH request as safe to repeat.
+    fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    async fn send(self) -> Result<reqwest::Response, FivetranError> {
+        let policy = &self.client.retry;
+        let inner = self.inner?;
+
+        let mut attempt = 1;
+        loop {
+            // bodies are always in-memory JSON, so cloning cannot fail
+            let req = inner.try_clone().unwrap();
+            let res = self.client.execute(req).await;
+
+            let (reason, retry_after) = match &res {
+                Ok(r) if retry::is_retryable_status(r.status()) => {
+                    let retry_after = r
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(retry::parse_retry_after);
+                    (r.status().to_string(), retry_after)
+                }
+                Err(FivetranError::Transport(e)) if retry::is_retryable_error(e) => {
+                    (e.to_string(), None)
+                }
+                _ => return res,
+            };
+
+            let status = res.as_ref().ok().map(|r| r.status());
+            let can_retry = policy.can_retry(&self.method, self.idempotent, status);
+            let delay = policy.delay(attempt, retry_after);
+            let Some(delay) = delay.filter(|_| can_retry) else {
+                return res;
+            };
+            log::warn!(
+                "  {} {}: {reason}, retrying in {delay:?} (attempt {}/{})",
+                self.method,
+                self.path,
+                attempt + 1,
+                policy.max_attempts
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
 }
 
 async fn receive_api_response<R: DeserializeOwned + std::fmt::Debug>(
     response: reqwest::Response,
-) -> anyhow::Result<R> {
+) -> Result<R, FivetranError> {
+    let status = response.status();
     if let Some(r) = receive_api_response_maybe(response).await? {
         Ok(r)
     } else {
-        Err(anyhow::anyhow!("request failed: no data"))
+        Err(FivetranError::Decode {
+            status,
+            path: "data".into(),
+            message: "missing field `data`".into(),
+            body: String::new(),
+        })
     }
 }
 
-async fn receive_api_response_empty(response: reqwest::Response) -> anyhow::Result<()> {
-    receive_api_response_maybe::<()>(response).await?;
+async fn receive_api_response_empty(response: reqwest::Response) -> Result<(), FivetranError> {
+    receive_api_response_maybe::<serde::de::IgnoredAny>(response).await?;
     Ok(())
 }
 
 async fn receive_api_response_maybe<R: DeserializeOwned + std::fmt::Debug>(
     response: reqwest::Response,
-) -> anyhow::Result<Option<R>> {
+) -> Result<Option<R>, FivetranError> {
     let status = response.status();
-    let r = response.json::<ApiResponse<R>>().await;
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+    let body = response.bytes().await?;
+
+    if !status.is_success() {
+        let err = FivetranError::from_status(status, &body, retry_after);
+        log::error!("  {err}");
+        return Err(err);
+    }
 
-    match r {
+    let de = &mut serde_json::Deserializer::from_slice(&body);
+    match serde_path_to_error::deserialize::<_, ApiResponse<R>>(de) {
         Err(err) => {
-            log::error!("  {} {:?}", status, err);
-            Err(anyhow::anyhow!("request failed"))
+            let err = FivetranError::Decode {
+                status,
+                path: err.path().to_string(),
+                message: err.into_inner().to_string(),
+                body: truncate_body(&body),
+            };
+            log::error!("  {err}");
+            Err(err)
         }
 
         Ok(r) => {
-            log::info!("  {} {:?}", status, r.message);
+            log::info!("  {} {} {:?}", status, r.code, r.message);
             Ok(r.data)
         }
     }
@@ -233,64 +993,187 @@ struct ApiResponse<R> {
     message: Option<String>,
 }
 
-// --- group ---
+/// One page of a list endpoint.
+#[derive(Deserialize, Debug)]
+struct Page<T> {
+    items: Vec<T>,
+    next_cursor: Option<String>,
+}
 
-async fn create_group(client: &Client) -> anyhow::Result<GroupResponse> {
-    let now = chrono::Utc::now();
-    let group_name = format!(
-        "test_{:04}_{:02}_{:02}T{:02}_{:02}_{:02}",
-        now.year(),
-        now.month(),
-        now.day(),
-        now.hour(),
-        now.minute(),
-        now.second()
-    );
+/// Maximum page size accepted by list endpoints.
+const PAGE_LIMIT: u32 = 1000;
+
+/// Lists all items of a list endpoint, following `next_cursor` until the
+/// last page. Pages are fetched lazily, as the stream is polled.
+///
+/// `request` builds the request for the first page; pagination parameters
+/// are added to it.
+fn list_paginated<'a, T, F>(request: F) -> impl Stream<Item = Result<T, FivetranError>> + 'a
+where
+    T: DeserializeOwned + std::fmt::Debug + 'a,
+    F: Fn() -> Request<'a> + 'a,
+{
+    // state: `None` when done, `Some(cursor)` when there is a page to fetch
+    let first_page: Option<Option<String>> = Some(None);
+
+    stream::try_unfold(first_page, move |state| {
+        let req = request();
+        async move {
+            let Some(cursor) = state else {
+                return Ok::<_, FivetranError>(None);
+            };
 
-    log::info!("create_group: {group_name}");
-    let res = client
-        .request(reqwest::Method::POST, "/v1/groups")
-        .json(&NewGroupRequest {
-            name: group_name.clone(),
-        })
-        .send()
-        .await?;
+            let mut req = req.query(&[("limit", PAGE_LIMIT.to_string())]);
+            if let Some(cursor) = cursor {
+                req = req.query(&[("cursor", cursor)]);
+            }
+            let page: Page<T> = receive_api_response(req.send().await?).await?;
 
-    receive_api_response(res).await
+            let items = stream::iter(page.items.into_iter().map(Ok::<T, FivetranError>));
+            Ok(Some((items, page.next_cursor.map(Some))))
+        }
+    })
+    .try_flatten()
 }
 
-#[derive(Serialize)]
-struct NewGroupRequest {
-    name: String,
+/// Body of a non-2xx response. Fivetran usually sends `code` and `message`,
+/// but proxies in front of it may reply with anything.
+#[derive(Deserialize, Debug, Default)]
+struct ApiErrorResponse {
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    message: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct GroupResponse {
-    id: String,
-    name: String,
-    created_at: String,
+/// How much of a response body is kept in errors.
+const ERROR_BODY_LIMIT: usize = 1024;
+
+fn truncate_body(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    if body.len() <= ERROR_BODY_LIMIT {
+        return body.into_owned();
+    }
+    let mut end = ERROR_BODY_LIMIT;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes total)", &body[..end], body.len())
 }
 
-async fn delete_group(client: &Client, group_id: &str) -> anyhow::Result<()> {
-    log::info!("delete_group: {group_id}");
+#[derive(Debug, thiserror::Error)]
+pub enum FivetranError {
+    /// The request never got a response: DNS, TLS, connection reset, timeout.
+    #[error("transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+
+    /// 401 or 403: the API key is missing, wrong or lacks permissions.
+    #[error("authentication failed ({status}): {code}: {message}")]
+    Unauthorized {
+        status: reqwest::StatusCode,
+        code: String,
+        message: String,
+    },
+
+    /// 404: the object does not exist (anymore).
+    #[error("not found: {code}: {message}")]
+    NotFound { code: String, message: String },
+
+    /// 429: too many requests.
+    #[error("rate limited (retry after {retry_after:?}): {message}")]
+    RateLimited {
+        retry_after: Option<String>,
+        message: String,
+    },
+
+    /// Any other non-2xx response.
+    #[error("request failed ({status}): {code}: {message}")]
+    Api {
+        status: reqwest::StatusCode,
+        code: String,
+        message: String,
+    },
+
+    /// The path of an endpoint does not make a URL with the base URL.
+    #[error("invalid URL for `{path}`: {message}")]
+    Url { path: String, message: String },
+
+    /// Recording to or replaying from a [Cassette] failed.
+    #[error("cassette: {0}")]
+    Cassette(String),
+
+    /// Response was 2xx, but its body does not match our model of the API.
+    #[error("cannot decode response ({status}) at `{path}`: {message}; body: {body}")]
+    Decode {
+        status: reqwest::StatusCode,
+        /// JSON path of the offending value, e.g. `data.status.setup_state`.
+        path: String,
+        message: String,
+        /// Response body, truncated to [ERROR_BODY_LIMIT] bytes.
+        body: String,
+    },
+}
 
-    let res = client
-        .request(reqwest::Method::DELETE, &format!("/v1/groups/{group_id}"))
-        .send()
-        .await?;
+impl FivetranError {
+    fn from_status(status: reqwest::StatusCode, body: &[u8], retry_after: Option<String>) -> Self {
+        let ApiErrorResponse { code, message } =
+            serde_json::from_slice(body).unwrap_or_else(|_| ApiErrorResponse {
+                code: String::new(),
+                message: truncate_body(body),
+            });
+
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                FivetranError::Unauthorized {
+                    status,
+                    code,
+                    message,
+                }
+            }
+            reqwest::StatusCode::NOT_FOUND => FivetranError::NotFound { code, message },
+            reqwest::StatusCode::TOO_MANY_REQUESTS => FivetranError::RateLimited {
+                retry_after,
+                message,
+            },
+            _ => FivetranError::Api {
+                status,
+                code,
+                message,
+            },
+        }
+    }
 
-    receive_api_response_empty(res).await
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, FivetranError::NotFound { .. })
+    }
 }
 
-async fn get_group(client: &Client, group_id: &str) -> anyhow::Result<GroupResponse> {
-    log::info!("get_group: {group_id}");
+// --- group ---
 
-    let res = client
-        .request(reqwest::Method::GET, &format!("/v1/groups/{group_id}"))
-        .send()
-        .await?;
+/// Creates a group named with the prefix, the time and a random suffix, as
+/// group names must be unique and several are created per second.
+async fn create_group(client: &Client) -> Result<api::GroupResponse, FivetranError> {
+    let now = client.now();
+    let group_name = format!(
+        "{}{:04}_{:02}_{:02}T{:02}_{:02}_{:02}_{:04x}",
+        cleanup::group_name_prefix(client),
+        now.year(),
+        now.month(),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        rand::random::<u16>()
+    );
 
-    receive_api_response(res).await
+    log::info!("create_group: {group_name}");
+    api::create_group(
+        client,
+        &api::NewGroupRequest {
+            name: Some(group_name),
+        },
+    )
+    .await
 }
 
 // --- destination ---
@@ -298,234 +1181,40 @@ async fn get_group(client: &Client, group_id: &str) -> anyhow::Result<GroupRespo
 async fn create_destination(
     client: &Client,
     group_id: &str,
-    pg_addr: SocketAddr,
-) -> anyhow::Result<DestinationExtendedResponse> {
+    pg: &Endpoint,
+) -> Result<api::DestinationExtendedResponse, FivetranError> {
     log::info!("create_destination");
 
-    let res = client
-        .request(reqwest::Method::POST, "/v1/destinations")
-        .json(&PostgresWarehouseNewDestinationRequest {
-            group_id: group_id.to_string(),
-            service: "postgres_warehouse".into(),
-            time_zone_offset: TimeZoneOffset::utc,
-            region: None,
-            trust_certificates: Some(true),
-            trust_fingerprints: Some(true),
-            run_setup_tests: Some(true),
-            daylight_saving_time_enabled: None,
-            hybrid_deployment_agent_id: None,
-            private_link_id: None,
-            proxy_agent_id: None,
-            config: PostgresWarehouseConfigV1Config {
-                host: Some(pg_addr.ip().to_string()),
-                port: Some(pg_addr.port() as i64),
-                user: Some("username".into()),
-                password: Some("pass".into()),
-                database: Some("postgres".into()),
-                always_encrypted: Some(false),
-                connection_type: Some(ConnectionType::Directly),
-                ..Default::default()
-            },
-        })
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-#[derive(Serialize)]
-struct PostgresWarehouseNewDestinationRequest {
-    group_id: String,
-    service: String,
-    time_zone_offset: TimeZoneOffset,
-    region: Option<Region>,
-    trust_certificates: Option<bool>,
-    trust_fingerprints: Option<bool>,
-    run_setup_tests: Option<bool>,
-    daylight_saving_time_enabled: Option<bool>,
-    hybrid_deployment_agent_id: Option<String>,
-    private_link_id: Option<String>,
-    proxy_agent_id: Option<String>,
-    config: PostgresWarehouseConfigV1Config,
-}
-
-#[derive(Serialize, Default)]
-struct PostgresWarehouseConfigV1Config {
-    tunnel_port: Option<i64>,
-    database: Option<String>,
-    password: Option<String>,
-    connection_type: Option<ConnectionType>,
-    port: Option<i64>,
-    host: Option<String>,
-    tunnel_host: Option<String>,
-    always_encrypted: Option<bool>,
-    user: Option<String>,
-    tunnel_user: Option<String>,
-}
-
-#[derive(Serialize)]
-#[serde(rename_all = "PascalCase")]
-enum ConnectionType {
-    Directly,
-    PrivateLink,
-    ProxyAgent,
-    SshTunnel,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[allow(non_camel_case_types)]
-enum Region {
-    AWS_AP_NORTHEAST_1,
-    AWS_AP_SOUTHEAST_1,
-    AWS_AP_SOUTHEAST_2,
-    AWS_AP_SOUTH_1,
-    AWS_CA_CENTRAL_1,
-    AWS_EU_CENTRAL_1,
-    AWS_EU_WEST_1,
-    AWS_EU_WEST_2,
-    AWS_US_EAST_1,
-    AWS_US_EAST_2,
-    AWS_US_GOV_WEST_1,
-    AWS_US_WEST_2,
-    AZURE_AUSTRALIAEAST,
-    AZURE_CANADACENTRAL,
-    AZURE_CENTRALINDIA,
-    AZURE_CENTRALUS,
-    AZURE_EASTUS,
-    AZURE_EASTUS2,
-    AZURE_JAPANEAST,
-    AZURE_SOUTHEASTASIA,
-    AZURE_UAENORTH,
-    AZURE_UKSOUTH,
-    AZURE_WESTEUROPE,
-    GCP_ASIA_NORTHEAST1,
-    GCP_ASIA_SOUTH1,
-    GCP_ASIA_SOUTHEAST1,
-    GCP_ASIA_SOUTHEAST2,
-    GCP_AUSTRALIA_SOUTHEAST1,
-    GCP_EUROPE_WEST2,
-    GCP_EUROPE_WEST3,
-    GCP_NORTHAMERICA_NORTHEAST1,
-    GCP_US_CENTRAL1,
-    GCP_US_EAST4,
-    GCP_US_WEST1,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[allow(non_camel_case_types)]
-enum TimeZoneOffset {
-    #[serde(rename = "-11")]
-    minus_11,
-    #[serde(rename = "-10")]
-    minus_10,
-    #[serde(rename = "-9")]
-    minus_9,
-    #[serde(rename = "-8")]
-    minus_8,
-    #[serde(rename = "-7")]
-    minus_7,
-    #[serde(rename = "-6")]
-    minus_6,
-    #[serde(rename = "-5")]
-    minus_5,
-    #[serde(rename = "-4")]
-    minus_4,
-    #[serde(rename = "-3")]
-    minus_3,
-    #[serde(rename = "-2")]
-    minus_2,
-    #[serde(rename = "-1")]
-    minus_1,
-    #[serde(rename = "0")]
-    utc,
-    #[serde(rename = "+1")]
-    plus_1,
-    #[serde(rename = "+2")]
-    plus_2,
-    #[serde(rename = "+3")]
-    plus_3,
-    #[serde(rename = "+4")]
-    plus_4,
-    #[serde(rename = "+5")]
-    plus_5,
-    #[serde(rename = "+6")]
-    plus_6,
-    #[serde(rename = "+7")]
-    plus_7,
-    #[serde(rename = "+8")]
-    plus_8,
-    #[serde(rename = "+9")]
-    plus_9,
-    #[serde(rename = "+10")]
-    plus_10,
-    #[serde(rename = "+11")]
-    plus_11,
-    #[serde(rename = "+12")]
-    plus_12,
-}
-
-#[derive(Deserialize, Debug)]
-#[allow(non_camel_case_types)]
-enum DestinationSetupStatus {
-    broken,
-    connected,
-    incomplete,
-}
-
-#[derive(Deserialize, Debug)]
-struct DestinationExtendedResponse {
-    id: String,
-    service: String,
-    region: Region,
-    setup_status: DestinationSetupStatus,
-    group_id: String,
-    time_zone_offset: TimeZoneOffset,
-    daylight_saving_time_enabled: Option<bool>,
-    // setup_tests: Option<list["SetupTestResultResponse"]>,
-    local_processing_agent_id: Option<String>,
-    private_link_id: Option<String>,
-    proxy_agent_id: Option<String>,
-    hybrid_deployment_agent_id: Option<String>,
-}
-
-async fn delete_destination(client: &Client, destination_id: &str) -> anyhow::Result<()> {
-    log::info!("delete_destination: {destination_id}");
-
-    let res = client
-        .request(
-            reqwest::Method::DELETE,
-            &format!("/v1/destinations/{destination_id}"),
-        )
-        .send()
-        .await?;
-
-    receive_api_response_empty(res).await
-}
-
-async fn list_destinations(client: &Client) -> anyhow::Result<ListDestinationResponse> {
-    log::info!("list_destinations");
-
-    let res = client
-        .request(reqwest::Method::GET, "/v1/destinations")
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-#[derive(Deserialize, Debug)]
-struct ListDestinationResponse {
-    items: Vec<DestinationResponse>,
-}
-
-#[derive(Deserialize, Debug)]
-struct DestinationResponse {
-    group_id: String,
-    id: String,
-    region: Region,
-    service: String,
-    setup_status: DestinationSetupStatus,
-    time_zone_offset: TimeZoneOffset,
+    let pg_addr = pg.addr();
+    let tunnel = pg.ssh_tunnel();
+
+    let request = api::PostgresWarehouseNewDestinationRequest {
+        group_id: group_id.to_string(),
+        service: "postgres_warehouse".into(),
+        time_zone_offset: api::NewDestinationRequestTimeZoneOffset::Value0,
+        region: None,
+        trust_certificates: Some(true),
+        trust_fingerprints: Some(true),
+        run_setup_tests: Some(true),
+        daylight_saving_time_enabled: None,
+        hybrid_deployment_agent_id: None,
+        networking_method: None,
+        private_link_id: None,
+        proxy_agent_id: None,
+        config: Some(api::PostgresWarehouseConfigV1Config {
+            host: Some(pg_addr.ip().to_string()),
+            port: Some(pg_addr.port().into()),
+            user: Some("username".into()),
+            password: Some("pass".into()),
+            database: Some("postgres".into()),
+            always_encrypted: Some(false),
+            connection_type: Some(pg.connection_type()),
+            tunnel_host: tunnel.map(|t| t.host.clone()),
+            tunnel_port: tunnel.map(|t| t.port.into()),
+            tunnel_user: tunnel.map(|t| t.user.clone()),
+        }),
+    };
+    api::create_destination(client, &request).await
 }
 
 // --- connection ---
@@ -533,415 +1222,161 @@ struct DestinationResponse {
 async fn create_connector(
     client: &Client,
     group_id: &str,
-    gel_addr: SocketAddr,
-) -> anyhow::Result<ConnectorResponseV1> {
-    log::info!("create_connection");
+    source: &Source<'_>,
+) -> Result<api::ConnectorResponseV1, FivetranError> {
+    log::info!(
+        "create_connection with update method {}",
+        source.update_method
+    );
+
+    let gel = source.endpoint;
+    let gel_addr = gel.addr();
+    let tunnel = gel.ssh_tunnel();
 
-    let config = PostgresConfigV1Config {
+    let config = api::PostgresNewConnectorRequestV1Config {
         host: Some(gel_addr.ip().to_string()),
-        port: Some(gel_addr.port()),
+        port: Some(gel_addr.port().into()),
         user: Some("edgedb".into()),
         password: Some("edgedb".into()),
-        database: Some("main".into()),
-        update_method: Some(PostgresConfigV1ConfigUpdateMethod::XMIN),
-        connection_type: Some(ConnectionType::Directly),
-        schema_prefix: "gel".into(),
+        database: Some(source.database.into()),
+        update_method: Some(source.update_method.api()),
+        replication_slot: source
+            .update_method
+            .replication_plugin()
+            .map(|_| REPLICATION_SLOT.into()),
+        publication_name: (source.update_method == UpdateMethod::WalPgoutput)
+            .then(|| PUBLICATION.into()),
+        connection_type: Some(gel.connection_type()),
+        tunnel_host: tunnel.map(|t| t.host.clone()),
+        tunnel_port: tunnel.map(|t| t.port.into()),
+        tunnel_user: tunnel.map(|t| t.user.clone()),
+        schema_prefix: Some(source.schema_prefix.into()),
         ..Default::default()
     };
 
-    let res = client
-        .request(reqwest::Method::POST, "/v1/connections")
-        .json(&PostgresNewConnectorRequestV1 {
-            group_id: Some(group_id.to_string()),
-            service: Some("postgres".into()),
-            trust_certificates: Some(true),
-            trust_fingerprints: Some(true),
-            run_setup_tests: Some(true),
-            paused: Some(true),
-            pause_after_trial: Some(true),
-            sync_frequency: Some(NewConnectorRequestV1SyncFrequency::Value15),
-            daily_sync_time: None,
-            config,
-        })
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-#[derive(Serialize, Default)]
-struct PostgresConfigV1Config {
-    publication_name: Option<String>,
-    connection_type: Option<ConnectionType>,
-    update_method: Option<PostgresConfigV1ConfigUpdateMethod>,
-    always_encrypted: Option<bool>,
-    tunnel_user: Option<String>,
-    client_public_certificate: Option<String>,
-    tunnel_port: Option<u16>,
-    // auth_method: Option<PostgresConfigV1ConfigAuthMethod>,
-    database: Option<String>,
-    password: Option<String>,
-    client_private_key: Option<String>,
-    port: Option<u16>,
-    host: Option<String>,
-    tunnel_host: Option<String>,
-    entra_tenant_id: Option<String>,
-    entra_app_id: Option<String>,
-    replication_slot: Option<String>,
-    user: Option<String>,
-
-    schema_prefix: String,
-}
-
-#[derive(Serialize)]
-#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
-enum PostgresConfigV1ConfigUpdateMethod {
-    TELEPORT,
-    WAL,
-    WAL_PGOUTPUT,
-    XMIN,
-}
-
-#[derive(Serialize)]
-struct PostgresNewConnectorRequestV1 {
-    group_id: Option<String>,
-    service: Option<String>,
-    trust_certificates: Option<bool>,
-    trust_fingerprints: Option<bool>,
-    run_setup_tests: Option<bool>,
-    paused: Option<bool>,
-    pause_after_trial: Option<bool>,
-    sync_frequency: Option<NewConnectorRequestV1SyncFrequency>,
-    // data_delay_sensitivity: Option<NewConnectorRequestV1DataDelaySensitivity>,
-    // data_delay_threshold: Option<int>,
-    daily_sync_time: Option<String>,
-    // schedule_type: Option<NewConnectorRequestV1ScheduleType>,
-    // connect_card_config: Option<"ConnectCardConfig">,
-    // proxy_agent_id: Option<String>,
-    // private_link_id: Option<String>,
-    // networking_method: Option<NewConnectorRequestV1NetworkingMethod>,
-    // hybrid_deployment_agent_id: Option<String>,
-    config: PostgresConfigV1Config,
-}
-
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Debug)]
-#[repr(u16)]
-enum NewConnectorRequestV1SyncFrequency {
-    Value1 = 1,
-    Value5 = 5,
-    Value15 = 15,
-    Value30 = 30,
-    Value60 = 60,
-    Value120 = 120,
-    Value180 = 180,
-    Value360 = 360,
-    Value480 = 480,
-    Value720 = 720,
-    Value1440 = 1440,
-}
-
-#[derive(Deserialize, Debug)]
-struct ConnectorResponseV1 {
-    id: String,
-    service: String,
-    schema: String,
-    paused: bool,
-    status: ConnectorStatusResponse,
-    sync_frequency: NewConnectorRequestV1SyncFrequency,
-    group_id: String,
-    service_version: i64,
-    created_at: String,
-    pause_after_trial: bool,
-    // data_delay_sensitivity: ConnectorResponseV1DataDelaySensitivity,
-    schedule_type: String,
-    daily_sync_time: Option<String>,
-    succeeded_at: Option<String>,
-    connected_by: Option<String>,
-    // setup_tests: Option<list["SetupTestResultResponse"]>,
-    // source_sync_details: Option<"ConnectorResponseV1SourceSyncDetails">,
-    failed_at: Option<String>,
-    private_link_id: Option<String>,
-    proxy_agent_id: Option<String>,
-    // networking_method: Option<ConnectorResponseV1NetworkingMethod>,
-    // connect_card: Option<"ConnectCardResponse">,
-    // data_delay_threshold: Option<int64>,
-    // connect_card_config: Option<"ConnectCardConfig">,
-    hybrid_deployment_agent_id: Option<String>,
-}
-
-#[derive(Deserialize, Debug)]
-struct ConnectorStatusResponse {
-    ///    update_state (str): The current data update state of the connection. The available values are: <br /> -
-    ///        on_schedule - the sync is running smoothly, no delays <br /> - delayed - the data is delayed for a longer time
-    ///        than expected for the update. Example: delayed.
-    update_state: String,
-
-    /// setup_state (str): The current setup state of the connection. The available values are: <br /> - incomplete -
-    ///         the setup config is incomplete, the setup tests never succeeded <br /> - connected - the connection is properly
-    ///         set up <br /> - broken - the connection setup config is broken. Example: connected.
-    setup_state: String,
-
-    /// sync_state (str): The current sync state of the connection. The available values are: <br /> - scheduled - the
-    ///         sync is waiting to be run <br /> - syncing - the sync is currently running <br /> - paused - the sync is
-    ///         currently paused <br /> - rescheduled - the sync is waiting until more API calls are available in the source
-    ///         service. Example: scheduled.
-    sync_state: String,
-
-    /// is_historical_sync (bool): The boolean specifying whether the connection should be triggered to re-sync all
-    ///         historical data. If you set this parameter to TRUE, the next scheduled sync will be historical. If the value is
-    ///         FALSE or not specified, the connection will not re-sync historical data. NOTE: When the value is TRUE, only the
-    ///         next scheduled sync will be historical, all subsequent ones will be incremental. This parameter is set to FALSE
-    ///         once the historical sync is completed.
-    is_historical_sync: bool,
-
-    // /// tasks (Union[Unset, list['Alert']]): The collection of tasks for the connection
-    // tasks: Union[Unset, list["Alert"]] = UNSET
-
-    // /// warnings (Union[Unset, list['Alert']]): The collection of warnings for the connection
-    // warnings: Union[Unset, list["Alert"]] = UNSET
-    /// schema_status (Union[Unset, str]): Schema status. Returned only for connectors that support [Universal Column
-    /// Masking flow](https://fivetran.com/docs/rest-api/tutorials/schema-status). Example: ready.
-    schema_status: Option<String>,
-
-    /// rescheduled_for (Union[Unset, datetime.datetime]): The scheduled time for the next sync when sync_state is
-    /// rescheduled. If schedule_type is manual, then the connection expects triggering the event at the designated time
-    /// through the [Sync Connection Data](https://fivetran.com/docs/rest-api/api-reference/connections/sync-connection)
-    /// endpoint. Example: 2024-12-01T15:43:29.013729Z.
-    rescheduled_for: Option<String>,
-}
-
-async fn start_sync(client: &Client, connection_id: &str) -> anyhow::Result<ConnectorResponseV1> {
-    log::info!("start_sync");
-
-    let res = client
-        .request(
-            reqwest::Method::PATCH,
-            &format!("/v1/connections/{connection_id}"),
-        )
-        .json(&UpdateConnectorRequest {
-            is_historical_sync: true,
-            paused: false,
-        })
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-#[derive(Serialize)]
-struct UpdateConnectorRequest {
-    is_historical_sync: bool,
-    paused: bool,
+    let request = api::PostgresNewConnectorRequestV1 {
+        group_id: group_id.to_string(),
+        service: "postgres".into(),
+        trust_certificates: Some(true),
+        trust_fingerprints: Some(true),
+        run_setup_tests: Some(true),
+        paused: Some(true),
+        pause_after_trial: Some(true),
+        sync_frequency: Some(api::NewConnectorRequestV1SyncFrequency::Value15),
+        config: Some(config),
+        ..Default::default()
+    };
+    api::create_connection(client, &request).await
 }
 
-async fn get_connector(
+async fn start_sync(
     client: &Client,
     connection_id: &str,
-) -> anyhow::Result<ConnectorResponseV1> {
-    log::info!("get_connection");
-
-    let res = client
-        .request(
-            reqwest::Method::GET,
-            &format!("/v1/connections/{connection_id}"),
-        )
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-async fn list_connectors(client: &Client) -> anyhow::Result<ConnectorList> {
-    log::info!("list_connectors");
-
-    let res = client
-        .request(reqwest::Method::GET, "/v1/connections")
-        .send()
-        .await?;
+) -> Result<api::ConnectorResponseV1, FivetranError> {
+    log::info!("start_sync");
 
-    receive_api_response(res).await
+    // new connections are paused and have not synced yet, so their first
+    // sync is a historical one
+    let request = api::UpdateConnectorRequest {
+        paused: Some(false),
+        ..Default::default()
+    };
+    let connector = api::modify_connection(client, connection_id, &request).await?;
+    sync(client, connection_id, false).await?;
+    Ok(connector)
 }
 
-async fn list_connectors_of_group(
+async fn pause(
     client: &Client,
-    group_id: &str,
-) -> anyhow::Result<ConnectorList> {
-    log::info!("list_connection_of_group");
-
-    let res = client
-        .request(
-            reqwest::Method::GET,
-            &format!("/v1/groups/{group_id}/connections"),
-        )
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-#[derive(Debug, Deserialize)]
-struct ConnectorList {
-    items: Vec<ConnectorResponse>,
-}
-
-#[derive(Debug, Deserialize)]
-struct ConnectorResponse {
-    id: String,
-    service: String,
-    schema: String,
-    paused: bool,
-    status: ConnectorStatusResponse,
-    sync_frequency: u64,
-    group_id: String,
-    connected_by: String,
-    created_at: String,
-    pause_after_trial: bool,
-    schedule_type: String,
-    // config: Option<ConnectorResponseConfig>,
-    daily_sync_time: Option<String>,
-    succeeded_at: Option<String>,
-    // setup_tests: Option<Vec<SetupTestResultResponse>>,
-    // source_sync_details: Option<"ConnectorResponseSourceSyncDetails">,
-    // service_version: Option<int>,
-    failed_at: Option<String>,
-    // private_link_id: Option<String>,
-    // proxy_agent_id: Option<String>,
-    // networking_method: Option<ConnectorResponseNetworkingMethod>,
-    // connect_card: Option<"ConnectCardResponse">,
-    // data_delay_threshold: Option<int>,
-    // data_delay_sensitivity: Option<ConnectorResponseDataDelaySensitivity>,
-    // connect_card_config: Option<"ConnectCardConfig">,
-    // hybrid_deployment_agent_id: Option<String>,
-}
-
-async fn delete_connector(client: &Client, connector_id: &str) -> anyhow::Result<()> {
-    log::info!("delete_connector");
-
-    let res = client
-        .request(
-            reqwest::Method::DELETE,
-            &format!("/v1/connections/{connector_id}"),
-        )
-        .send()
-        .await?;
+    connection_id: &str,
+) -> Result<api::ConnectorResponseV1, FivetranError> {
+    log::info!("pause");
 
-    receive_api_response_empty(res).await
+    let request = api::UpdateConnectorRequest {
+        paused: Some(true),
+        ..Default::default()
+    };
+    api::modify_connection(client, connection_id, &request).await
 }
 
-// --- schema config reload ---
-
-async fn reload_connector_schema_config(
+async fn get_connector(
     client: &Client,
     connection_id: &str,
-) -> anyhow::Result<StandardConfigResponse> {
-    log::info!("reload_connection_schema_config");
-
-    let res = client
-        .request(
-            reqwest::Method::POST,
-            &format!("/v1/connections/{connection_id}/schemas/reload"),
-        )
-        .json(&ReloadStandardConfigRequest {})
-        .send()
-        .await?;
-
-    receive_api_response(res).await
+) -> Result<api::ConnectorResponseV1, FivetranError> {
+    api::connection_details(client, connection_id).await
 }
 
-#[derive(Serialize)]
-struct ReloadStandardConfigRequest {}
+/// Triggers a sync now, instead of waiting for the schedule.
+/// With `force`, a sync that is already running is restarted.
+async fn sync(client: &Client, connection_id: &str, force: bool) -> Result<(), FivetranError> {
+    log::info!("sync (force: {force})");
 
-#[derive(Debug, Deserialize)]
-struct StandardConfigResponse {
-    schemas: HashMap<String, SchemaConfigResponse>,
-    // schema_change_handling: StandardConfigResponseSchemaChangeHandling,
-    enable_new_by_default: Option<bool>,
+    let request = api::SyncConnectorRequest { force: Some(force) };
+    api::sync_connection(client, connection_id, &request).await
 }
 
-#[derive(Debug, Deserialize)]
-struct SchemaConfigResponse {
-    name_in_destination: String,
-    enabled: bool,
-    tables: HashMap<String, TableConfigResponse>,
-}
-
-#[derive(Debug, Deserialize)]
-struct TableConfigResponse {
-    name_in_destination: String,
-    enabled: bool,
-    columns: HashMap<String, ColumnConfigResponse>,
-    // enabled_patch_settings: "TableEnabledPatchSettings",
-    // sync_mode: Option<TableConfigResponseSyncMode>,
-    supports_columns_config: Option<bool>,
-}
+/// Triggers a historical sync of all tables of the connection.
+async fn resync(client: &Client, connection_id: &str) -> Result<(), FivetranError> {
+    log::info!("resync");
 
-#[derive(Debug, Deserialize)]
-struct ColumnConfigResponse {
-    name_in_destination: String,
-    enabled: bool,
-    hashed: bool,
-    // enabled_patch_settings: "ColumnEnabledPatchSettings",
-    is_primary_key: Option<bool>,
+    let request = api::ResyncConnectorRequest { scope: None };
+    api::resync_connection(client, connection_id, &request).await
 }
 
-// --- schema config update ---
-
-async fn update_connector_schema_config(
+/// Triggers a historical sync of some tables of the connection.
+async fn resync_tables(
     client: &Client,
     connection_id: &str,
-    request: &UpdateConnectorSchemaRequest,
-) -> anyhow::Result<StandardConfigResponse> {
-    log::info!("update_connector_schema_config");
-
-    let res = client
-        .request(
-            reqwest::Method::PATCH,
-            &format!("/v1/connections/{connection_id}/schemas"),
-        )
-        .json(request)
-        .send()
-        .await?;
-
-    receive_api_response(res).await
-}
-
-#[derive(Serialize)]
-struct UpdateConnectorSchemaRequest {
-    schema_change_handling: SchemaChangeHandling,
-    schemas: HashMap<String, UpdateConnectorSchema>,
-}
-
-/// The possible values for the schema_change_handling parameter are as follows:
-#[derive(Serialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-enum SchemaChangeHandling {
-    /// all new schemas, tables, and columns which appear in the source after the initial setup are included in syncs
-    AllowAll,
-    /// all new schemas and tables which appear in the source after the initial setup are excluded from syncs, but new columns are included
-    AllowColumns,
-    /// all new schemas, tables, and columns which appear in the source after the initial setup are excluded from syncs
-    BlockAll,
+    tables: &[SourceTable],
+) -> Result<(), FivetranError> {
+    log::info!("resync_tables: {tables:?}");
+
+    let mut scope: HashMap<String, Vec<String>> = HashMap::new();
+    for t in tables {
+        scope
+            .entry(t.schema.clone())
+            .or_default()
+            .push(t.table.clone());
+    }
+    api::resync_tables(client, connection_id, &scope).await
 }
 
-#[derive(Serialize)]
-struct UpdateConnectorSchema {
-    enabled: bool,
-    tables: HashMap<String, UpdateConnectorTable>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Serialize)]
-struct UpdateConnectorTable {
-    enabled: bool,
-    columns: HashMap<String, UpdateConnectorColumn>,
-}
-
-#[derive(Serialize)]
-struct UpdateConnectorColumn {
-    enabled: bool,
+    fn client(base_url: &str) -> Client {
+        let config = ClientConfig {
+            base_url: reqwest::Url::parse(base_url).unwrap(),
+            ..ClientConfig::new(Auth::Header("Bearer test".into()))
+        };
+        Client::from_config(&config).unwrap()
+    }
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    hashed: Option<bool>,
+    #[test]
+    fn paths_are_joined_below_the_base_url() {
+        let url = |base, path| client(base).url(path).unwrap().to_string();
+        assert_eq!(
+            url("https://api.fivetran.com", "/v1/groups"),
+            "https://api.fivetran.com/v1/groups"
+        );
+        assert_eq!(
+            url("http://proxy/fivetran", "/v1/groups?limit=10"),
+            "http://proxy/fivetran/v1/groups?limit=10"
+        );
+        assert_eq!(
+            url("http://proxy/fivetran/", "/v1/groups"),
+            "http://proxy/fivetran/v1/groups"
+        );
+    }
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    is_primary_key: Option<bool>,
+    #[tokio::test]
+    async fn invalid_urls_are_errors() {
+        let client = client("https://api.fivetran.com");
+        let res = client
+            .request(reqwest::Method::GET, "http://[not a host")
+            .send()
+            .await;
+        assert!(matches!(res, Err(FivetranError::Url { .. })), "{res:?}");
+    }
 }
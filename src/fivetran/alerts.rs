@@ -0,0 +1,66 @@
+use super::api::{Alert, ConnectorResponseV1, ConnectorStatusResponse};
+
+/// A sync ended with `failed_at` set.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "sync of connection {connection_id} failed at {failed_at}{}",
+    describe_status(.status)
+)]
+pub struct SyncFailed {
+    pub connection_id: String,
+    pub failed_at: String,
+    /// Status of the connection after the failure, with Fivetran's tasks
+    /// and warnings that tell why it failed.
+    pub status: ConnectorStatusResponse,
+}
+
+impl SyncFailed {
+    pub fn new(connector: ConnectorResponseV1) -> Self {
+        SyncFailed {
+            connection_id: connector.id,
+            failed_at: connector.failed_at.unwrap_or_default(),
+            status: connector.status,
+        }
+    }
+}
+
+fn describe_status(status: &ConnectorStatusResponse) -> String {
+    let mut r = format!(
+        "\n  setup_state: {}, sync_state: {}, update_state: {}",
+        status.setup_state, status.sync_state, status.update_state
+    );
+    let tasks = status.tasks.as_deref().unwrap_or_default();
+    let warnings = status.warnings.as_deref().unwrap_or_default();
+    if tasks.is_empty() && warnings.is_empty() {
+        r += "\n  no tasks or warnings";
+    }
+    for alert in tasks {
+        r += &format!("\n  task {}", describe(alert));
+    }
+    for alert in warnings {
+        r += &format!("\n  warning {}", describe(alert));
+    }
+    r
+}
+
+fn describe(alert: &Alert) -> String {
+    let mut r = format!(
+        "{}: {}",
+        alert.code.as_deref().unwrap_or("<no code>"),
+        alert.message.as_deref().unwrap_or("")
+    );
+    if let Some(details) = &alert.details {
+        r += &format!(" ({details})");
+    }
+    r
+}
+
+/// Logs tasks and warnings of a connection that did not fail.
+pub fn log(connection_id: &str, status: &ConnectorStatusResponse) {
+    for alert in status.tasks.as_deref().unwrap_or_default() {
+        log::warn!("connection {connection_id}: task {}", describe(alert));
+    }
+    for alert in status.warnings.as_deref().unwrap_or_default() {
+        log::warn!("connection {connection_id}: warning {}", describe(alert));
+    }
+}
@@ -0,0 +1,16 @@
+//! Fivetran REST API types and endpoints, generated by `build.rs` from
+//! `fivetran_openapi_v1.json`.
+
+#![allow(clippy::all, dead_code, non_camel_case_types)]
+
+use std::collections::HashMap;
+
+use futures::Stream;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+use super::{
+    Client, FivetranError, list_paginated, receive_api_response, receive_api_response_empty,
+};
+
+include!(concat!(env!("OUT_DIR"), "/fivetran_api.rs"));
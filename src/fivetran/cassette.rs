@@ -0,0 +1,368 @@
+//! Recording and replaying of Fivetran API traffic.
+//!
+//! A cassette is a JSON file with every request the [super::Client] made and
+//! the response it got, in order. Replaying it serves the same responses
+//! again, so a failed run can be reproduced without Fivetran.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::FivetranError;
+
+/// Value that replaces secrets in recorded headers.
+const REDACTED: &str = "<redacted>";
+
+/// Request and response headers that never end up in a cassette as they
+/// are.
+const SECRET_HEADERS: &[reqwest::header::HeaderName] = &[
+    reqwest::header::AUTHORIZATION,
+    reqwest::header::COOKIE,
+    reqwest::header::PROXY_AUTHORIZATION,
+    reqwest::header::SET_COOKIE,
+];
+
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    file: Mutex<CassetteFile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CassetteFile {
+    /// When recording started. During replay, this is "now".
+    recorded_at: DateTime<Utc>,
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+
+    /// Already served during replay.
+    #[serde(skip)]
+    used: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    /// Path and query, e.g. `/v1/connections?limit=1000`.
+    url: String,
+    headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<RecordedBody>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: RecordedBody,
+}
+
+/// JSON bodies are stored as JSON, so cassettes are readable and diffable.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordedBody {
+    Json(serde_json::Value),
+    Text(String),
+}
+
+impl RecordedBody {
+    fn new(bytes: &[u8]) -> Self {
+        match serde_json::from_slice(bytes) {
+            Ok(json) => RecordedBody::Json(json),
+            Err(_) => RecordedBody::Text(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            RecordedBody::Json(json) => serde_json::to_vec(json).unwrap(),
+            RecordedBody::Text(text) => text.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl Cassette {
+    /// Starts a new cassette at `path`, overwriting any existing file.
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Cassette {
+            path: path.into(),
+            mode: Mode::Record,
+            file: Mutex::new(CassetteFile {
+                recorded_at: Utc::now(),
+                interactions: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn replay(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let file = std::fs::read(&path)
+            .map_err(|e| anyhow::anyhow!("cannot read cassette {}: {e}", path.display()))?;
+        let file: CassetteFile = serde_json::from_slice(&file)
+            .map_err(|e| anyhow::anyhow!("cannot parse cassette {}: {e}", path.display()))?;
+        Ok(Cassette {
+            path,
+            mode: Mode::Replay,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Time the cassette was recorded, so time-dependent decisions (like
+    /// which objects are old) come out the same during replay.
+    pub fn recorded_at(&self) -> DateTime<Utc> {
+        self.file.lock().unwrap().recorded_at
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == Mode::Replay
+    }
+
+    pub(super) async fn execute(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, FivetranError> {
+        match self.mode {
+            Mode::Record => self.record_one(client, request).await,
+            Mode::Replay => self.replay_one(request),
+        }
+    }
+
+    async fn record_one(
+        &self,
+        client: &reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, FivetranError> {
+        let recorded_request = RecordedRequest::new(&request);
+
+        let response = client.execute(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        let interaction = Interaction {
+            request: recorded_request,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                headers: recorded_headers(&headers),
+                body: RecordedBody::new(&body),
+            },
+            used: true,
+        };
+        {
+            let mut file = self.file.lock().unwrap();
+            file.interactions.push(interaction);
+
+            // written after every response, so crashed runs leave a cassette too
+            let json = serde_json::to_vec_pretty(&*file).unwrap();
+            std::fs::write(&self.path, json).map_err(|e| {
+                FivetranError::Cassette(format!("cannot write {}: {e}", self.path.display()))
+            })?;
+        }
+
+        let mut response = http::Response::new(body);
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        Ok(response.into())
+    }
+
+    /// Serves the first unused response that was recorded for the same
+    /// method and URL. Requests to different endpoints may interleave
+    /// differently than during recording, but each endpoint sees its
+    /// responses in the recorded order.
+    fn replay_one(&self, request: reqwest::Request) -> Result<reqwest::Response, FivetranError> {
+        let method = request.method().as_str();
+        let url = path_and_query(request.url());
+
+        let mut file = self.file.lock().unwrap();
+        let interaction = file
+            .interactions
+            .iter_mut()
+            .find(|i| !i.used && i.request.method == method && i.request.url == url)
+            .ok_or_else(|| {
+                FivetranError::Cassette(format!("no recorded response left for {method} {url}"))
+            })?;
+        interaction.used = true;
+
+        let recorded = &interaction.response;
+        let status = reqwest::StatusCode::from_u16(recorded.status)
+            .map_err(|e| FivetranError::Cassette(format!("bad recorded status: {e}")))?;
+
+        let mut response = http::Response::new(recorded.body.to_bytes());
+        *response.status_mut() = status;
+        for (name, value) in &recorded.headers {
+            // the body is re-serialized, so its length may differ
+            if name == reqwest::header::CONTENT_LENGTH.as_str() {
+                continue;
+            }
+            let (Ok(name), Ok(value)) = (
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()),
+                reqwest::header::HeaderValue::from_str(value),
+            ) else {
+                continue;
+            };
+            response.headers_mut().append(name, value);
+        }
+        Ok(response.into())
+    }
+
+    /// Number of recorded responses that were not served during replay.
+    pub fn unused(&self) -> usize {
+        let file = self.file.lock().unwrap();
+        file.interactions.iter().filter(|i| !i.used).count()
+    }
+}
+
+impl RecordedRequest {
+    fn new(request: &reqwest::Request) -> Self {
+        RecordedRequest {
+            method: request.method().to_string(),
+            url: path_and_query(request.url()),
+            headers: recorded_headers(request.headers()),
+            body: request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .map(RecordedBody::new),
+        }
+    }
+}
+
+fn path_and_query(url: &reqwest::Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    }
+}
+
+/// All headers in order, repeated ones included, with secrets redacted.
+fn recorded_headers(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(name) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use axum::response::AppendHeaders;
+    use reqwest::header;
+
+    use super::*;
+
+    /// Serves `GET /v1/groups` with two cookies and a repeated header.
+    async fn serve() -> std::net::SocketAddr {
+        let app = axum::Router::new().route(
+            "/v1/groups",
+            axum::routing::get(async || {
+                (
+                    AppendHeaders([
+                        (header::SET_COOKIE, "session=response-secret-1"),
+                        (header::SET_COOKIE, "tracking=response-secret-2"),
+                        (header::VARY, "Accept"),
+                        (header::VARY, "Origin"),
+                    ]),
+                    axum::Json(serde_json::json!({ "code": "Success" })),
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    #[tokio::test]
+    async fn recorded_cassette_has_no_credentials() {
+        let addr = serve().await;
+        let path = std::env::temp_dir().join(format!("cassette-{}.json", std::process::id()));
+        let client = reqwest::Client::new();
+
+        let cassette = Cassette::record(&path);
+        let request = client
+            .get(format!("http://{addr}/v1/groups"))
+            .header(header::AUTHORIZATION, "Basic request-secret-1")
+            .header(header::COOKIE, "session=request-secret-2")
+            .header(header::PROXY_AUTHORIZATION, "Basic request-secret-3")
+            .build()
+            .unwrap();
+        let response = cassette.execute(&client, request).await.unwrap();
+        // the caller still sees the real response
+        assert_eq!(
+            response
+                .headers()
+                .get_all(header::SET_COOKIE)
+                .iter()
+                .count(),
+            2
+        );
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        for secret in ["request-secret", "response-secret"] {
+            assert!(
+                !recorded.contains(secret),
+                "{secret} in cassette:\n{recorded}"
+            );
+        }
+        let file: CassetteFile = serde_json::from_str(&recorded).unwrap();
+        let interaction = &file.interactions[0];
+        let values = |headers: &[(String, String)], name: &header::HeaderName| -> Vec<String> {
+            headers
+                .iter()
+                .filter(|(n, _)| n == name.as_str())
+                .map(|(_, v)| v.clone())
+                .collect()
+        };
+        for name in [
+            header::AUTHORIZATION,
+            header::COOKIE,
+            header::PROXY_AUTHORIZATION,
+        ] {
+            assert_eq!(values(&interaction.request.headers, &name), [REDACTED]);
+        }
+        let response_headers = &interaction.response.headers;
+        assert_eq!(
+            values(response_headers, &header::SET_COOKIE),
+            [REDACTED, REDACTED]
+        );
+        assert_eq!(
+            values(response_headers, &header::VARY),
+            ["Accept", "Origin"]
+        );
+
+        // replay serves repeated headers, too
+        let cassette = Cassette::replay(&path).unwrap();
+        let request = client
+            .get(format!("http://{addr}/v1/groups"))
+            .build()
+            .unwrap();
+        let response = cassette.execute(&client, request).await.unwrap();
+        let vary: Vec<_> = response.headers().get_all(header::VARY).iter().collect();
+        assert_eq!(vary, ["Accept", "Origin"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+//! Deletion of Fivetran objects left behind by earlier runs.
+//!
+//! Only groups named with the client's group prefix count as ours, so
+//! groups of others on a shared account are never touched. Until the next
+//! release, groups named `test_<time>` by runners from before the prefix
+//! count as ours too.
+
+use std::time::Duration;
+
+use chrono::TimeDelta;
+use futures::TryStreamExt;
+
+use super::{Client, FivetranError, api, ignore_not_found};
+use crate::report::log_table;
+
+/// Which groups [cleanup_old] deletes.
+#[derive(Debug, Clone)]
+pub struct CleanupOld {
+    /// Groups created longer ago than this are deleted.
+    pub max_age: Duration,
+    /// Only list what would be deleted.
+    pub dry_run: bool,
+}
+
+impl Default for CleanupOld {
+    fn default() -> Self {
+        CleanupOld {
+            max_age: Duration::from_secs(15 * 60),
+            dry_run: false,
+        }
+    }
+}
+
+/// Outcome of [cleanup_old].
+#[derive(Debug, Default)]
+pub struct Cleanup {
+    /// Our groups, in the order they were listed.
+    pub groups: Vec<OldGroup>,
+    /// Number of groups that we did not create.
+    pub foreign: usize,
+}
+
+#[derive(Debug)]
+pub struct OldGroup {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub action: Action,
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Removed,
+    WouldRemove,
+    Kept(String),
+    Failed(FivetranError),
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Removed => write!(f, "removed"),
+            Action::WouldRemove => write!(f, "would remove"),
+            Action::Kept(reason) => write!(f, "kept, {reason}"),
+            Action::Failed(e) => write!(f, "FAILED: {e}"),
+        }
+    }
+}
+
+/// Start of the names of groups that the client creates.
+pub(super) fn group_name_prefix(client: &Client) -> String {
+    format!("{}_test_", client.group_prefix)
+}
+
+/// Whether a group was created by a runner from before the group prefix,
+/// which named groups like `test_2024_01_31T12_00_00`. Only for the next
+/// release, which cleans them up.
+fn is_legacy(name: &str) -> bool {
+    name.strip_prefix("test_").is_some_and(|time| {
+        chrono::NaiveDateTime::parse_from_str(time, "%Y_%m_%dT%H_%M_%S").is_ok()
+    })
+}
+
+/// Deletes our groups that are older than `options.max_age`, with their
+/// destinations and connections. Goes on with the other groups when one
+/// cannot be deleted, see [Cleanup::into_result].
+///
+/// Groups with a `created_at` that cannot be parsed are kept.
+pub async fn cleanup_old(client: &Client, options: &CleanupOld) -> Result<Cleanup, FivetranError> {
+    let prefix = group_name_prefix(client);
+    let max_age = TimeDelta::from_std(options.max_age).unwrap_or(TimeDelta::MAX);
+    log::info!(
+        "removing groups named {prefix}* or test_<time> created more than {} minutes ago",
+        max_age.num_minutes()
+    );
+
+    // collect everything before deleting, so removed items don't shift pages
+    let groups: Vec<_> = api::list_all_groups(client).try_collect().await?;
+    let mut cleanup = Cleanup::default();
+    for group in groups {
+        if !group.name.starts_with(&prefix) && !is_legacy(&group.name) {
+            cleanup.foreign += 1;
+            continue;
+        }
+
+        let created_at = chrono::DateTime::parse_from_str(&group.created_at, "%+");
+        let action = match created_at.map(|c| client.now().signed_duration_since(c)) {
+            Err(e) => Action::Kept(format!("invalid created_at: {e}")),
+            Ok(age) if age <= max_age => {
+                Action::Kept(format!("created {} minutes ago", age.num_minutes()))
+            }
+            Ok(_) if options.dry_run => Action::WouldRemove,
+            Ok(_) => {
+                log::info!("removing group {} ({})", group.id, group.name);
+                match ignore_not_found(remove(client, &group.id).await) {
+                    Ok(()) => Action::Removed,
+                    Err(e) => Action::Failed(e),
+                }
+            }
+        };
+        cleanup.groups.push(OldGroup {
+            id: group.id,
+            name: group.name,
+            created_at: group.created_at,
+            action,
+        });
+    }
+    Ok(cleanup)
+}
+
+/// Deletes a group with its connections and destination.
+async fn remove(client: &Client, group_id: &str) -> Result<(), FivetranError> {
+    let connections: Vec<_> = api::list_all_connections_in_group(client, group_id, None)
+        .try_collect()
+        .await?;
+    for connection in &connections {
+        ignore_not_found(api::delete_connection(client, &connection.id).await)?;
+    }
+    // a destination has the id of its group
+    ignore_not_found(api::delete_destination(client, group_id).await)?;
+    api::delete_group(client, group_id).await
+}
+
+impl Cleanup {
+    pub fn removed(&self) -> impl Iterator<Item = &OldGroup> {
+        self.groups
+            .iter()
+            .filter(|g| matches!(g.action, Action::Removed))
+    }
+
+    /// Logs a table of our groups and what happened to them.
+    pub fn log(&self) {
+        let count = |f: fn(&Action) -> bool| self.groups.iter().filter(|g| f(&g.action)).count();
+        log::info!(
+            "cleanup_old: removed {}, would remove {}, kept {}, failed {}; \
+             ignored {} groups of others",
+            count(|a| matches!(a, Action::Removed)),
+            count(|a| matches!(a, Action::WouldRemove)),
+            count(|a| matches!(a, Action::Kept(_))),
+            count(|a| matches!(a, Action::Failed(_))),
+            self.foreign,
+        );
+        if self.groups.is_empty() {
+            return;
+        }
+
+        let actions: Vec<String> = self.groups.iter().map(|g| g.action.to_string()).collect();
+        let mut table = vec![vec!["group", "id", "created_at", "action"]];
+        for (group, action) in self.groups.iter().zip(&actions) {
+            table.push(vec![&group.name, &group.id, &group.created_at, action]);
+        }
+        log_table(&table);
+    }
+
+    pub fn into_result(self) -> anyhow::Result<()> {
+        let failed: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|g| matches!(g.action, Action::Failed(_)))
+            .map(|g| format!("group {}", g.id))
+            .collect();
+        anyhow::ensure!(failed.is_empty(), "could not delete {}", failed.join(", "));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_groups_are_named_with_a_time() {
+        assert!(is_legacy("test_2025_06_30T23_59_01"));
+        assert!(!is_legacy("test_foreign_0"));
+        assert!(!is_legacy("test_2025_06_30T23_59_01_extra"));
+        assert!(!is_legacy("gel_test_2025_06_30T23_59_01"));
+        assert!(!is_legacy("2025_06_30T23_59_01"));
+    }
+}
@@ -0,0 +1,362 @@
+//! Settings of the Fivetran API [super::Client].
+//!
+//! Each setting is taken from the first source that has it:
+//! 1. command line flags,
+//! 2. environment variables (`FIVETRAN_API_KEY`, `FIVETRAN_BASE_URL`, ...),
+//! 3. a TOML config file, given with `--fivetran-config` or `FIVETRAN_CONFIG`,
+//! 4. defaults.
+//!
+//! Credentials are the exception: they all come from the first source that
+//! has any, so e.g. a key in the config file does not win over an
+//! `Authorization` header from the environment.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::Engine;
+use serde::Deserialize;
+
+use super::RetryPolicy;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.fivetran.com";
+
+/// Fully resolved client settings.
+#[derive(Clone)]
+pub struct ClientConfig {
+    pub base_url: reqwest::Url,
+    pub auth: Auth,
+    /// Timeout of a whole request, including reading the response.
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub user_agent: String,
+    /// Sent with requests whose endpoint does not ask for a specific version.
+    pub accept: String,
+    /// Start of the names of created groups. `cleanup_old` only deletes
+    /// groups whose names start with it.
+    pub group_prefix: String,
+    pub retry: RetryPolicy,
+}
+
+#[derive(Clone)]
+pub enum Auth {
+    /// API key and secret, sent as HTTP Basic auth.
+    KeySecret { key: String, secret: String },
+    /// Complete value of the `Authorization` header.
+    Header(String),
+}
+
+impl Auth {
+    pub fn header_value(&self) -> String {
+        match self {
+            Auth::KeySecret { key, secret } => {
+                let credentials =
+                    base64::engine::general_purpose::STANDARD.encode(format!("{key}:{secret}"));
+                format!("Basic {credentials}")
+            }
+            Auth::Header(header) => header.clone(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Default settings for the given credentials.
+    pub fn new(auth: Auth) -> Self {
+        ClientConfig {
+            base_url: reqwest::Url::parse(DEFAULT_BASE_URL).unwrap(),
+            auth,
+            timeout: Duration::from_secs(60),
+            connect_timeout: Duration::from_secs(10),
+            user_agent: concat!("gel-fivetran-tests/", env!("CARGO_PKG_VERSION")).into(),
+            accept: "application/json;version=2".into(),
+            group_prefix: "gel".into(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Resolves settings from command line flags, environment and config file.
+    pub fn load(flags: ClientOptions) -> anyhow::Result<Self> {
+        let mut options = flags.or(ClientOptions::from_env()?);
+        if let Some(path) = options.config.clone() {
+            options = options.or(ClientOptions::from_file(&path)?);
+        }
+        options.resolve()
+    }
+}
+
+/// Client settings as given by one source, all of them optional.
+#[derive(Default, Deserialize, clap::Args)]
+#[command(next_help_heading = "Fivetran API")]
+#[serde(deny_unknown_fields)]
+pub struct ClientOptions {
+    /// TOML file with any of the settings below, in snake_case
+    #[arg(long = "fivetran-config", value_name = "FILE", global = true)]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// Fivetran API key
+    #[arg(long = "fivetran-api-key", value_name = "KEY", global = true)]
+    pub api_key: Option<String>,
+
+    /// Fivetran API secret
+    #[arg(long = "fivetran-api-secret", value_name = "SECRET", global = true)]
+    pub api_secret: Option<String>,
+
+    /// Complete `Authorization` header, instead of key and secret
+    #[arg(long = "fivetran-authorization", value_name = "HEADER", global = true)]
+    pub authorization: Option<String>,
+
+    /// Fivetran API URL, e.g. of a staging environment or a mock server
+    #[arg(long = "fivetran-base-url", value_name = "URL", global = true)]
+    pub base_url: Option<String>,
+
+    /// Request timeout, in seconds
+    #[arg(long = "fivetran-timeout", value_name = "SECS", global = true)]
+    pub timeout: Option<u64>,
+
+    /// Connect timeout, in seconds
+    #[arg(long = "fivetran-connect-timeout", value_name = "SECS", global = true)]
+    pub connect_timeout: Option<u64>,
+
+    /// `User-Agent` header
+    #[arg(long = "fivetran-user-agent", value_name = "AGENT", global = true)]
+    pub user_agent: Option<String>,
+
+    /// `Accept` header for endpoints without a specific version
+    #[arg(long = "fivetran-accept", value_name = "MEDIA_TYPE", global = true)]
+    pub accept: Option<String>,
+
+    /// Start of the names of created groups, to tell them apart from groups
+    /// of others on the same account
+    #[arg(long = "fivetran-group-prefix", value_name = "PREFIX", global = true)]
+    pub group_prefix: Option<String>,
+
+    /// How often to repeat a failed request, 0 to never retry
+    #[arg(long = "fivetran-max-retries", value_name = "N", global = true)]
+    pub max_retries: Option<u32>,
+
+    /// Delay before the first retry, in milliseconds. Doubles with each
+    /// following retry.
+    #[arg(long = "fivetran-retry-base-ms", value_name = "MS", global = true)]
+    pub retry_base_ms: Option<u64>,
+
+    /// Also retry POST and PATCH requests that are not known to be safe to
+    /// repeat
+    #[arg(long = "fivetran-retry-post", value_name = "BOOL", global = true)]
+    pub retry_post: Option<bool>,
+}
+
+impl ClientOptions {
+    pub fn from_env() -> anyhow::Result<Self> {
+        fn var(name: &str) -> anyhow::Result<Option<String>> {
+            match env::var(name) {
+                Ok(value) => Ok(Some(value)),
+                Err(env::VarError::NotPresent) => Ok(None),
+                Err(e) => Err(anyhow::anyhow!("{name}: {e}")),
+            }
+        }
+        fn parse<T>(name: &str, what: &str) -> anyhow::Result<Option<T>>
+        where
+            T: std::str::FromStr,
+            T::Err: std::fmt::Display,
+        {
+            var(name)?
+                .map(|v| v.parse())
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("{name} must be {what}: {e}"))
+        }
+        fn secs(name: &str) -> anyhow::Result<Option<u64>> {
+            parse(name, "a number of seconds")
+        }
+
+        Ok(ClientOptions {
+            config: var("FIVETRAN_CONFIG")?.map(PathBuf::from),
+            api_key: var("FIVETRAN_API_KEY")?,
+            api_secret: var("FIVETRAN_API_SECRET")?,
+            authorization: var("FIVETRAN_AUTHORIZATION")?,
+            base_url: var("FIVETRAN_BASE_URL")?,
+            timeout: secs("FIVETRAN_TIMEOUT")?,
+            connect_timeout: secs("FIVETRAN_CONNECT_TIMEOUT")?,
+            user_agent: var("FIVETRAN_USER_AGENT")?,
+            accept: var("FIVETRAN_ACCEPT")?,
+            group_prefix: var("FIVETRAN_GROUP_PREFIX")?,
+            max_retries: parse("FIVETRAN_MAX_RETRIES", "a number")?,
+            retry_base_ms: parse("FIVETRAN_RETRY_BASE_MS", "a number of milliseconds")?,
+            retry_post: parse("FIVETRAN_RETRY_POST", "true or false")?,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        toml::from_str(&content).map_err(|e| anyhow::anyhow!("invalid {}: {e}", path.display()))
+    }
+
+    fn has_credentials(&self) -> bool {
+        self.api_key.is_some() || self.api_secret.is_some() || self.authorization.is_some()
+    }
+
+    /// Fills settings missing in `self` from `other`, and credentials if
+    /// `self` has none.
+    fn or(self, other: ClientOptions) -> ClientOptions {
+        let credentials = if self.has_credentials() {
+            &self
+        } else {
+            &other
+        };
+        let (api_key, api_secret, authorization) = (
+            credentials.api_key.clone(),
+            credentials.api_secret.clone(),
+            credentials.authorization.clone(),
+        );
+        ClientOptions {
+            config: self.config.or(other.config),
+            api_key,
+            api_secret,
+            authorization,
+            base_url: self.base_url.or(other.base_url),
+            timeout: self.timeout.or(other.timeout),
+            connect_timeout: self.connect_timeout.or(other.connect_timeout),
+            user_agent: self.user_agent.or(other.user_agent),
+            accept: self.accept.or(other.accept),
+            group_prefix: self.group_prefix.or(other.group_prefix),
+            max_retries: self.max_retries.or(other.max_retries),
+            retry_base_ms: self.retry_base_ms.or(other.retry_base_ms),
+            retry_post: self.retry_post.or(other.retry_post),
+        }
+    }
+
+    fn resolve(self) -> anyhow::Result<ClientConfig> {
+        let auth = match (self.api_key, self.api_secret, self.authorization) {
+            (Some(key), Some(secret), _) => Auth::KeySecret { key, secret },
+            (Some(_), None, _) => anyhow::bail!(
+                "Fivetran API key is set, but secret is not: \
+                 use --fivetran-api-secret or FIVETRAN_API_SECRET"
+            ),
+            (None, Some(_), _) => anyhow::bail!(
+                "Fivetran API secret is set, but key is not: \
+                 use --fivetran-api-key or FIVETRAN_API_KEY"
+            ),
+            (None, None, Some(header)) => Auth::Header(header),
+            (None, None, None) => anyhow::bail!(
+                "missing Fivetran credentials: set FIVETRAN_API_KEY and FIVETRAN_API_SECRET \
+                 (or FIVETRAN_AUTHORIZATION), use --fivetran-api-key and --fivetran-api-secret, \
+                 or put api_key and api_secret into the --fivetran-config file"
+            ),
+        };
+
+        let mut config = ClientConfig::new(auth);
+        if let Some(base_url) = self.base_url {
+            config.base_url = reqwest::Url::parse(&base_url)
+                .map_err(|e| anyhow::anyhow!("invalid Fivetran base URL `{base_url}`: {e}"))?;
+        }
+        if let Some(timeout) = self.timeout {
+            config.timeout = Duration::from_secs(timeout);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            config.connect_timeout = Duration::from_secs(connect_timeout);
+        }
+        if let Some(user_agent) = self.user_agent {
+            config.user_agent = user_agent;
+        }
+        if let Some(accept) = self.accept {
+            config.accept = accept;
+        }
+        if let Some(group_prefix) = self.group_prefix {
+            // Fivetran only accepts letters, digits and underscores in group names
+            anyhow::ensure!(
+                !group_prefix.is_empty()
+                    && group_prefix
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "invalid Fivetran group prefix `{group_prefix}`: \
+                 use only letters, digits and underscores"
+            );
+            config.group_prefix = group_prefix;
+        }
+        if let Some(max_retries) = self.max_retries {
+            config.retry.max_attempts = max_retries.saturating_add(1);
+        }
+        if let Some(retry_base_ms) = self.retry_base_ms {
+            config.retry.base_delay = Duration::from_millis(retry_base_ms);
+        }
+        if let Some(retry_post) = self.retry_post {
+            config.retry.retry_non_idempotent = retry_post;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> ClientOptions {
+        ClientOptions {
+            api_key: Some(key.into()),
+            api_secret: Some("secret".into()),
+            ..Default::default()
+        }
+    }
+
+    fn header(header: &str) -> ClientOptions {
+        ClientOptions {
+            authorization: Some(header.into()),
+            ..Default::default()
+        }
+    }
+
+    fn resolve(flags: ClientOptions, env: ClientOptions, file: ClientOptions) -> String {
+        let config = flags.or(env).or(file).resolve().unwrap();
+        config.auth.header_value()
+    }
+
+    #[test]
+    fn credentials_come_from_the_first_source_with_any() {
+        let basic = |key: &str| {
+            Auth::KeySecret {
+                key: key.into(),
+                secret: "secret".into(),
+            }
+            .header_value()
+        };
+        let none = ClientOptions::default;
+
+        assert_eq!(
+            resolve(none(), header("Bearer env"), key("file")),
+            "Bearer env"
+        );
+        assert_eq!(
+            resolve(header("Bearer flag"), key("env"), none()),
+            "Bearer flag"
+        );
+        assert_eq!(
+            resolve(key("flag"), header("Bearer env"), none()),
+            basic("flag")
+        );
+        assert_eq!(resolve(none(), none(), key("file")), basic("file"));
+
+        // a key without secret is not completed from another source
+        let flags = ClientOptions {
+            api_key: Some("flag".into()),
+            ..Default::default()
+        };
+        assert!(flags.or(key("env")).resolve().is_err());
+    }
+
+    #[test]
+    fn other_settings_come_from_the_first_source_with_each() {
+        let env = ClientOptions {
+            timeout: Some(5),
+            ..header("Bearer env")
+        };
+        let file = ClientOptions {
+            timeout: Some(7),
+            connect_timeout: Some(3),
+            ..key("file")
+        };
+        let config = ClientOptions::default().or(env).or(file).resolve().unwrap();
+        assert_eq!(config.timeout, Duration::from_secs(5));
+        assert_eq!(config.connect_timeout, Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,1417 @@
+//! In-process emulator of the parts of the Fivetran REST API that the runner
+//! uses, so `setup_sync`, `cleanup` and `cleanup_old` can run without
+//! credentials or network.
+//!
+//! Objects go through the same states as on the real service:
+//! - a new connection has `setup_state: incomplete` and becomes `connected`
+//!   (or `broken`, see [MockConfig::setup_test_status]) after
+//!   [MockConfig::setup_polls] reads,
+//! - un-pausing a connection schedules a sync, which is `syncing` for
+//!   [MockConfig::sync_polls] reads and then sets `succeeded_at` or `failed_at`,
+//! - each successful sync moves the xmin cursor in the connection state.
+//!
+//! Failures can be injected per endpoint with [Failure].
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::{env, fmt};
+
+use axum::body::Bytes;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::api;
+
+pub struct MockConfig {
+    /// Number of connection reads before `setup_state` becomes `connected`.
+    pub setup_polls: u32,
+
+    /// Number of connection reads during which a sync is `syncing`.
+    pub sync_polls: u32,
+
+    /// Result of the last setup test of destinations and connections.
+    /// With `Failed`, their setup ends up broken.
+    pub setup_test_status: api::SetupTestResultResponseStatus,
+
+    /// Finish syncs with `failed_at` instead of `succeeded_at`.
+    pub sync_fails: bool,
+
+    /// Responses returned instead of handling matching requests.
+    pub failures: Vec<Failure>,
+
+    /// Number of groups (each with a destination and a connection) that
+    /// exist before the run and are old enough for `cleanup_old`. They are
+    /// named like groups of a client with the default group prefix, every
+    /// other one like those of runners from before the prefix.
+    pub stale_groups: usize,
+
+    /// Number of groups like the stale ones, but named like groups of
+    /// someone else on the account, so `cleanup_old` has to keep them.
+    pub foreign_groups: usize,
+
+    /// Largest page returned by list endpoints, regardless of `limit`.
+    pub max_page_size: usize,
+
+    /// Source schema, as returned when the schema config is reloaded.
+    pub schema: api::StandardConfigResponse,
+
+    /// Serve the connection state endpoints. Fivetran itself only does so
+    /// for Function and Connector SDK connections and answers 405 otherwise,
+    /// so this is off unless a run checks the rewind path.
+    pub connection_state: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            setup_polls: 1,
+            sync_polls: 2,
+            setup_test_status: api::SetupTestResultResponseStatus::Passed,
+            sync_fails: false,
+            failures: Vec::new(),
+            stale_groups: 0,
+            foreign_groups: 0,
+            max_page_size: 100,
+            schema: default_schema(),
+            connection_state: false,
+        }
+    }
+}
+
+impl MockConfig {
+    /// Reads overrides from the environment:
+    /// - `FIVETRAN_MOCK_FAIL`: `;`-separated list of [Failure]s,
+    /// - `FIVETRAN_MOCK_SYNC=fail`: syncs fail,
+    /// - `FIVETRAN_MOCK_SETUP`: `warning` or `failed` setup tests,
+    /// - `FIVETRAN_MOCK_STALE`: number of stale groups,
+    /// - `FIVETRAN_MOCK_FOREIGN`: number of old groups of someone else,
+    /// - `FIVETRAN_MOCK_STATE=supported`: connection state is available.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = MockConfig::default();
+        if let Ok(failures) = env::var("FIVETRAN_MOCK_FAIL") {
+            config.failures = failures
+                .split(';')
+                .filter(|f| !f.trim().is_empty())
+                .map(Failure::from_str)
+                .collect::<Result<_, _>>()?;
+        }
+        if let Ok(sync) = env::var("FIVETRAN_MOCK_SYNC") {
+            config.sync_fails = match sync.as_str() {
+                "fail" => true,
+                "succeed" => false,
+                _ => anyhow::bail!("FIVETRAN_MOCK_SYNC must be `fail` or `succeed`"),
+            };
+        }
+        if let Ok(setup) = env::var("FIVETRAN_MOCK_SETUP") {
+            config.setup_test_status = serde_json::from_value(json!(setup.to_uppercase()))
+                .map_err(|_| {
+                    anyhow::anyhow!("FIVETRAN_MOCK_SETUP must be `passed`, `warning` or `failed`")
+                })?;
+        }
+        if let Ok(stale) = env::var("FIVETRAN_MOCK_STALE") {
+            config.stale_groups = stale.parse()?;
+        }
+        if let Ok(foreign) = env::var("FIVETRAN_MOCK_FOREIGN") {
+            config.foreign_groups = foreign.parse()?;
+        }
+        if let Ok(state) = env::var("FIVETRAN_MOCK_STATE") {
+            config.connection_state = match state.as_str() {
+                "supported" => true,
+                "unsupported" => false,
+                _ => anyhow::bail!("FIVETRAN_MOCK_STATE must be `supported` or `unsupported`"),
+            };
+        }
+        Ok(config)
+    }
+}
+
+/// An injected error response.
+///
+/// Parsed from `[METHOD] PATH STATUS [xTIMES] [retry-after=SECS]`, for example
+/// `PATCH /v1/connections 503 x2`. `PATH` matches as a prefix.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// `None` matches any method.
+    pub method: Option<Method>,
+    pub path_prefix: String,
+    pub status: StatusCode,
+    /// How many matching requests fail. `None` means all of them.
+    pub times: Option<u32>,
+    /// Value of the `Retry-After` header, in seconds.
+    pub retry_after: Option<u64>,
+}
+
+impl Failure {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        self.times != Some(0)
+            && self.method.as_ref().is_none_or(|m| m == method)
+            && path.starts_with(&self.path_prefix)
+    }
+}
+
+impl FromStr for Failure {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace().peekable();
+
+        let method = match parts.peek() {
+            Some(p) if !p.starts_with('/') => Some(Method::from_str(parts.next().unwrap())?),
+            _ => None,
+        };
+        let path_prefix = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("missing path in failure `{s}`"))?
+            .to_string();
+        let status = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("missing status in failure `{s}`"))?;
+        let status = StatusCode::from_u16(status.parse()?)?;
+
+        let mut failure = Failure {
+            method,
+            path_prefix,
+            status,
+            times: None,
+            retry_after: None,
+        };
+        for part in parts {
+            if let Some(times) = part.strip_prefix('x') {
+                failure.times = Some(times.parse()?);
+            } else if let Some(secs) = part.strip_prefix("retry-after=") {
+                failure.retry_after = Some(secs.parse()?);
+            } else {
+                anyhow::bail!("unexpected `{part}` in failure `{s}`");
+            }
+        }
+        Ok(failure)
+    }
+}
+
+/// A running emulator. Stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Shared,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start(config: MockConfig) -> anyhow::Result<Self> {
+        let mut state = MockState {
+            config,
+            next_id: 0,
+            groups: BTreeMap::new(),
+            destinations: BTreeMap::new(),
+            connections: BTreeMap::new(),
+        };
+        state.seed_stale();
+        let state: Shared = Arc::new(Mutex::new(state));
+
+        let app = Router::new()
+            .route("/v1/groups", get(list_groups).post(create_group))
+            .route("/v1/groups/{id}", get(group_details).delete(delete_group))
+            .route("/v1/groups/{id}/connections", get(list_group_connections))
+            .route("/v1/groups/{id}/public-key", get(group_public_key))
+            .route(
+                "/v1/destinations",
+                get(list_destinations).post(create_destination),
+            )
+            .route(
+                "/v1/destinations/{id}",
+                get(destination_details).delete(delete_destination),
+            )
+            .route(
+                "/v1/connections",
+                get(list_connections).post(create_connection),
+            )
+            .route(
+                "/v1/connections/{id}",
+                get(connection_details)
+                    .patch(modify_connection)
+                    .delete(delete_connection),
+            )
+            .route(
+                "/v1/connections/{id}/schemas",
+                get(schema_config).patch(modify_schema_config),
+            )
+            .route("/v1/connections/{id}/schemas/reload", post(reload_schema))
+            .route("/v1/connections/{id}/sync", post(sync_connection))
+            .route("/v1/connections/{id}/resync", post(resync_connection))
+            .route(
+                "/v1/connections/{id}/schemas/tables/resync",
+                post(resync_tables),
+            )
+            .route(
+                "/v1/connections/{id}/state",
+                get(connection_state).patch(modify_connection_state),
+            )
+            .fallback(|| async { MockError::not_found("endpoint", "") })
+            .layer(middleware::from_fn_with_state(state.clone(), intercept))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("mock fivetran server: {e}");
+            }
+        });
+        log::info!("mock fivetran server listening on {addr}");
+
+        Ok(MockServer { addr, state, task })
+    }
+
+    pub fn url(&self) -> reqwest::Url {
+        reqwest::Url::parse(&format!("http://{}", self.addr)).unwrap()
+    }
+
+    /// Number of groups, destinations and connections that currently exist.
+    pub fn object_counts(&self) -> (usize, usize, usize) {
+        let state = self.state.lock().unwrap();
+        (
+            state.groups.len(),
+            state.destinations.len(),
+            state.connections.len(),
+        )
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+type Shared = Arc<Mutex<MockState>>;
+
+struct MockState {
+    config: MockConfig,
+    next_id: u64,
+    groups: BTreeMap<String, api::GroupResponse>,
+    /// Keyed by id, which (as in Fivetran) equals the id of its group.
+    destinations: BTreeMap<String, api::DestinationExtendedResponse>,
+    connections: BTreeMap<String, Connection>,
+}
+
+struct Connection {
+    response: api::ConnectorResponseV1,
+    /// Set after the first schema reload.
+    schema: Option<api::StandardConfigResponse>,
+    sync_pending: bool,
+    /// Connector state, with the xmin cursor.
+    state: Value,
+    /// Reads since the last state transition.
+    polls: u32,
+}
+
+impl MockState {
+    fn next_id(&mut self, kind: &str) -> String {
+        self.next_id += 1;
+        format!("mock_{kind}_{}", self.next_id)
+    }
+
+    fn seed_stale(&mut self) {
+        let created_at = (chrono::Utc::now() - chrono::Duration::days(1)).to_rfc3339();
+        for i in 0..self.config.stale_groups {
+            let name = if i % 2 == 0 {
+                format!("gel_test_stale_{i}")
+            } else {
+                format!("test_2020_01_01T00_00_{:02}", i % 60)
+            };
+            self.seed_group(name, &created_at);
+        }
+        for i in 0..self.config.foreign_groups {
+            self.seed_group(format!("test_foreign_{i}"), &created_at);
+        }
+    }
+
+    /// Adds a group with a destination and a connected connection.
+    fn seed_group(&mut self, name: String, created_at: &str) {
+        let group_id = self.next_id("group");
+        self.groups.insert(
+            group_id.clone(),
+            api::GroupResponse {
+                id: group_id.clone(),
+                name,
+                created_at: created_at.to_string(),
+            },
+        );
+        let destination = convert(json!({
+            "group_id": group_id,
+            "service": "postgres_warehouse",
+            "time_zone_offset": "0",
+        }));
+        self.destinations.insert(
+            group_id.clone(),
+            new_destination(destination, api::SetupTestResultResponseStatus::Passed),
+        );
+
+        let id = self.next_id("connection");
+        let mut response = new_connection(&id, &group_id, created_at, "stale".into());
+        response.status.setup_state = "connected".into();
+        self.connections.insert(
+            id,
+            Connection {
+                response,
+                schema: None,
+                sync_pending: false,
+                state: json!({}),
+                polls: 0,
+            },
+        );
+    }
+
+    fn connection(&mut self, id: &str) -> Result<&mut Connection, MockError> {
+        self.connections
+            .get_mut(id)
+            .ok_or_else(|| MockError::not_found("Connection", id))
+    }
+}
+
+impl Connection {
+    /// Moves the connection one step further, as if time has passed since
+    /// it was last looked at.
+    fn advance(&mut self, config: &MockConfig) {
+        self.polls += 1;
+        let status = &mut self.response.status;
+
+        if status.setup_state == "incomplete" {
+            if self.polls >= config.setup_polls {
+                let tests = setup_tests(config.setup_test_status);
+                status.setup_state = if is_broken(&tests) {
+                    "broken".into()
+                } else {
+                    "connected".into()
+                };
+                self.response.setup_tests = Some(tests);
+                self.polls = 0;
+            }
+            return;
+        }
+
+        match status.sync_state.as_str() {
+            "scheduled" if self.sync_pending && !self.response.paused => {
+                status.sync_state = "syncing".into();
+                self.sync_pending = false;
+                self.polls = 0;
+            }
+            "syncing" if self.polls >= config.sync_polls => {
+                status.sync_state = "scheduled".into();
+                status.is_historical_sync = false;
+                self.polls = 0;
+
+                let now = Some(now());
+                if config.sync_fails {
+                    self.response.failed_at = now;
+                    status.tasks = Some(vec![api::Alert {
+                        code: Some("mock_sync_failed".into()),
+                        message: Some("Injected sync failure".into()),
+                        details: Some("FIVETRAN_MOCK_SYNC=fail".into()),
+                    }]);
+                } else {
+                    self.response.succeeded_at = now;
+                    self.advance_cursor();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Logs requests, checks authorization and injects configured failures.
+async fn intercept(State(state): State<Shared>, req: Request, next: Next) -> Response {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    {
+        let mut state = state.lock().unwrap();
+        log::debug!("mock fivetran server: {method} {path}");
+
+        if !req.headers().contains_key(header::AUTHORIZATION) {
+            return MockError {
+                status: StatusCode::UNAUTHORIZED,
+                code: "AuthFailed",
+                message: "Missing Authorization header".into(),
+            }
+            .into_response();
+        }
+
+        let failure = state
+            .config
+            .failures
+            .iter_mut()
+            .find(|f| f.matches(&method, &path));
+        if let Some(failure) = failure {
+            if let Some(times) = &mut failure.times {
+                *times -= 1;
+            }
+            log::debug!("mock fivetran server: injecting {failure:?}");
+            let error = MockError {
+                status: failure.status,
+                code: "InjectedFailure",
+                message: format!("Injected failure for {method} {path}"),
+            };
+            let mut response = error.into_response();
+            if let Some(secs) = failure.retry_after {
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, secs.to_string().parse().unwrap());
+            }
+            return response;
+        }
+    }
+    next.run(req).await
+}
+
+type Reply = Result<Response, MockError>;
+
+struct MockError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl MockError {
+    fn not_found(kind: &str, id: &str) -> Self {
+        MockError {
+            status: StatusCode::NOT_FOUND,
+            code: "NotFound_Entity",
+            message: format!("{kind} with id '{id}' doesn't exist"),
+        }
+    }
+
+    fn invalid(message: impl fmt::Display) -> Self {
+        MockError {
+            status: StatusCode::BAD_REQUEST,
+            code: "InvalidInput",
+            message: message.to_string(),
+        }
+    }
+
+    fn conflict(message: impl fmt::Display) -> Self {
+        MockError {
+            status: StatusCode::CONFLICT,
+            code: "AlreadyExists",
+            message: message.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for MockError {
+    fn into_response(self) -> Response {
+        let body = json!({ "code": self.code, "message": self.message });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+fn success(status: StatusCode, data: impl Serialize) -> Reply {
+    let body = json!({ "code": "Success", "data": data });
+    Ok((status, Json(body)).into_response())
+}
+
+fn deleted(kind: &str) -> Reply {
+    let body = json!({ "code": "Success", "message": format!("{kind} has been deleted") });
+    Ok(Json(body).into_response())
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, MockError> {
+    serde_json::from_slice(body).map_err(MockError::invalid)
+}
+
+/// Converts between generated types that have the same JSON representation.
+fn convert<T: DeserializeOwned>(value: impl Serialize) -> T {
+    serde_json::from_value(serde_json::to_value(value).unwrap()).unwrap()
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+// --- lists ---
+
+#[derive(Deserialize)]
+struct ListQuery {
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+fn page<T: Serialize>(items: Vec<T>, query: &ListQuery, max_page_size: usize) -> Reply {
+    let start: usize = match &query.cursor {
+        Some(cursor) => cursor
+            .parse()
+            .map_err(|_| MockError::invalid("Invalid cursor"))?,
+        None => 0,
+    };
+    let limit = query.limit.unwrap_or(100).clamp(1, max_page_size);
+    let end = (start + limit).min(items.len());
+
+    let next_cursor = (end < items.len()).then(|| end.to_string());
+    let items: Vec<_> = items.into_iter().skip(start).take(limit).collect();
+    success(
+        StatusCode::OK,
+        json!({ "items": items, "next_cursor": next_cursor }),
+    )
+}
+
+/// Connections as returned by list endpoints.
+fn list_item(c: &Connection) -> api::ConnectorResponse {
+    let mut item: HashMap<String, Value> = convert(&c.response);
+    item.entry("connected_by".into())
+        .or_insert_with(|| json!("mock_user"));
+    convert(item)
+}
+
+// --- groups ---
+
+async fn create_group(State(state): State<Shared>, body: Bytes) -> Reply {
+    let req: api::NewGroupRequest = parse(&body)?;
+    let name = req
+        .name
+        .ok_or_else(|| MockError::invalid("Field 'name' is required"))?;
+
+    let mut state = state.lock().unwrap();
+    if state.groups.values().any(|g| g.name == name) {
+        return Err(MockError::conflict(format!(
+            "Group with name '{name}' already exists"
+        )));
+    }
+    let id = state.next_id("group");
+    let group = api::GroupResponse {
+        id: id.clone(),
+        name,
+        created_at: now(),
+    };
+    state.groups.insert(id, group.clone());
+    success(StatusCode::CREATED, group)
+}
+
+async fn list_groups(State(state): State<Shared>, Query(query): Query<ListQuery>) -> Reply {
+    let state = state.lock().unwrap();
+    let items: Vec<_> = state.groups.values().cloned().collect();
+    page(items, &query, state.config.max_page_size)
+}
+
+async fn group_details(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let state = state.lock().unwrap();
+    let group = state
+        .groups
+        .get(&id)
+        .ok_or_else(|| MockError::not_found("Group", &id))?;
+    success(StatusCode::OK, group)
+}
+
+async fn delete_group(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    state
+        .groups
+        .remove(&id)
+        .ok_or_else(|| MockError::not_found("Group", &id))?;
+
+    // the destination and connections go together with the group
+    state.destinations.remove(&id);
+    state.connections.retain(|_, c| c.response.group_id != id);
+    deleted("Group")
+}
+
+async fn list_group_connections(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Reply {
+    let state = state.lock().unwrap();
+    if !state.groups.contains_key(&id) {
+        return Err(MockError::not_found("Group", &id));
+    }
+    let items = state
+        .connections
+        .values()
+        .filter(|c| c.response.group_id == id)
+        .map(list_item)
+        .collect();
+    page(items, &query, state.config.max_page_size)
+}
+
+async fn group_public_key(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let state = state.lock().unwrap();
+    if !state.groups.contains_key(&id) {
+        return Err(MockError::not_found("Group", &id));
+    }
+    let public_key = format!("ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQmock fivetran-{id}");
+    success(StatusCode::OK, json!({ "public_key": public_key }))
+}
+
+// --- destinations ---
+
+fn new_destination(
+    req: api::NewDestinationRequest,
+    setup_test_status: api::SetupTestResultResponseStatus,
+) -> api::DestinationExtendedResponse {
+    let tests = setup_tests(setup_test_status);
+    api::DestinationExtendedResponse {
+        id: req.group_id.clone(),
+        group_id: req.group_id,
+        service: req.service,
+        region: req
+            .region
+            .unwrap_or(api::DestinationExtendedResponseRegion::GcpUsEast4),
+        setup_status: if is_broken(&tests) {
+            api::DestinationExtendedResponseSetupStatus::Broken
+        } else {
+            api::DestinationExtendedResponseSetupStatus::Connected
+        },
+        setup_tests: req.run_setup_tests.unwrap_or(false).then_some(tests),
+        time_zone_offset: req.time_zone_offset,
+        daylight_saving_time_enabled: req.daylight_saving_time_enabled,
+        hybrid_deployment_agent_id: req.hybrid_deployment_agent_id,
+        local_processing_agent_id: None,
+        networking_method: req.networking_method.map(convert),
+        private_link_id: req.private_link_id,
+        proxy_agent_id: req.proxy_agent_id,
+    }
+}
+
+async fn create_destination(State(state): State<Shared>, body: Bytes) -> Reply {
+    let req: api::NewDestinationRequest = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    let group_id = req.group_id.clone();
+    if !state.groups.contains_key(&group_id) {
+        return Err(MockError::not_found("Group", &group_id));
+    }
+    if state.destinations.contains_key(&group_id) {
+        return Err(MockError::conflict(format!(
+            "Group '{group_id}' already has a destination"
+        )));
+    }
+    let destination = new_destination(req, state.config.setup_test_status);
+    state.destinations.insert(group_id, destination.clone());
+    success(StatusCode::CREATED, destination)
+}
+
+async fn destination_details(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let state = state.lock().unwrap();
+    let destination = state
+        .destinations
+        .get(&id)
+        .ok_or_else(|| MockError::not_found("Destination", &id))?;
+    success(StatusCode::OK, destination)
+}
+
+async fn delete_destination(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    state
+        .destinations
+        .remove(&id)
+        .ok_or_else(|| MockError::not_found("Destination", &id))?;
+    deleted("Destination")
+}
+
+async fn list_destinations(State(state): State<Shared>, Query(query): Query<ListQuery>) -> Reply {
+    let state = state.lock().unwrap();
+    let items: Vec<api::DestinationResponse> = state.destinations.values().map(convert).collect();
+    page(items, &query, state.config.max_page_size)
+}
+
+// --- connections ---
+
+fn new_connection(
+    id: &str,
+    group_id: &str,
+    created_at: &str,
+    schema: String,
+) -> api::ConnectorResponseV1 {
+    api::ConnectorResponseV1 {
+        id: id.to_string(),
+        group_id: group_id.to_string(),
+        service: "postgres".into(),
+        service_version: 1,
+        schema,
+        created_at: created_at.to_string(),
+        connected_by: Some("mock_user".into()),
+        paused: true,
+        pause_after_trial: false,
+        schedule_type: "auto".into(),
+        sync_frequency: api::ConnectorResponseV1SyncFrequency::Value360,
+        data_delay_sensitivity: api::ConnectorResponseV1DataDelaySensitivity::Normal,
+        status: api::ConnectorStatusResponse {
+            setup_state: "incomplete".into(),
+            sync_state: "paused".into(),
+            update_state: "on_schedule".into(),
+            is_historical_sync: true,
+            tasks: Some(Vec::new()),
+            warnings: Some(Vec::new()),
+            rescheduled_for: None,
+            schema_status: None,
+        },
+        connect_card: None,
+        connect_card_config: None,
+        daily_sync_time: None,
+        data_delay_threshold: None,
+        failed_at: None,
+        succeeded_at: None,
+        hybrid_deployment_agent_id: None,
+        networking_method: None,
+        private_link_id: None,
+        proxy_agent_id: None,
+        setup_tests: None,
+        source_sync_details: None,
+    }
+}
+
+async fn create_connection(State(state): State<Shared>, body: Bytes) -> Reply {
+    let req: api::NewConnectorRequestV1 = parse(&body)?;
+    let config: Value = parse::<Value>(&body)?["config"].clone();
+
+    // database connectors are named by their prefix, others by `schema`
+    let schema = config["schema_prefix"]
+        .as_str()
+        .or(config["schema"].as_str())
+        .ok_or_else(|| MockError::invalid("Field 'config.schema_prefix' is required"))?
+        .to_string();
+
+    let mut state = state.lock().unwrap();
+    if !state.groups.contains_key(&req.group_id) {
+        return Err(MockError::not_found("Group", &req.group_id));
+    }
+    let taken = state
+        .connections
+        .values()
+        .any(|c| c.response.group_id == req.group_id && c.response.schema == schema);
+    if taken {
+        return Err(MockError::conflict(format!(
+            "Connection with schema '{schema}' already exists in group '{}'",
+            req.group_id
+        )));
+    }
+
+    let id = state.next_id("connection");
+    let mut response = new_connection(&id, &req.group_id, &now(), schema);
+    response.service = req.service;
+    response.paused = req.paused.unwrap_or(false);
+    response.pause_after_trial = req.pause_after_trial.unwrap_or(false);
+    if let Some(sync_frequency) = req.sync_frequency {
+        response.sync_frequency = sync_frequency;
+    }
+    if let Some(schedule_type) = req.schedule_type {
+        response.schedule_type = convert(schedule_type);
+    }
+    if !response.paused {
+        response.status.sync_state = "scheduled".into();
+    }
+
+    let connection = Connection {
+        response: response.clone(),
+        schema: None,
+        sync_pending: !response.paused,
+        state: json!({}),
+        polls: 0,
+    };
+    state.connections.insert(id, connection);
+    success(StatusCode::CREATED, response)
+}
+
+async fn connection_details(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    let state = &mut *state;
+    let connection = state
+        .connections
+        .get_mut(&id)
+        .ok_or_else(|| MockError::not_found("Connection", &id))?;
+    connection.advance(&state.config);
+    success(StatusCode::OK, &connection.response)
+}
+
+async fn modify_connection(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Reply {
+    let req: api::UpdateConnectorRequest = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    let connection = state.connection(&id)?;
+    let response = &mut connection.response;
+
+    if let Some(paused) = req.paused {
+        if response.paused && !paused {
+            connection.sync_pending = true;
+            response.status.sync_state = "scheduled".into();
+        } else if paused {
+            response.status.sync_state = "paused".into();
+        }
+        response.paused = paused;
+    }
+    if let Some(is_historical_sync) = req.is_historical_sync {
+        response.status.is_historical_sync = is_historical_sync;
+    }
+    if let Some(pause_after_trial) = req.pause_after_trial {
+        response.pause_after_trial = pause_after_trial;
+    }
+    if let Some(sync_frequency) = req.sync_frequency {
+        response.sync_frequency = convert(sync_frequency);
+    }
+    if let Some(schedule_type) = req.schedule_type {
+        response.schedule_type = convert(schedule_type);
+    }
+    if let Some(daily_sync_time) = req.daily_sync_time {
+        response.daily_sync_time = Some(daily_sync_time);
+    }
+    success(StatusCode::OK, &*response)
+}
+
+async fn delete_connection(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    state
+        .connections
+        .remove(&id)
+        .ok_or_else(|| MockError::not_found("Connection", &id))?;
+    deleted("Connection")
+}
+
+async fn list_connections(State(state): State<Shared>, Query(query): Query<ListQuery>) -> Reply {
+    let state = state.lock().unwrap();
+    let items = state.connections.values().map(list_item).collect();
+    page(items, &query, state.config.max_page_size)
+}
+
+// --- syncs ---
+
+impl Connection {
+    fn schedule_sync(&mut self) {
+        self.sync_pending = true;
+        if !self.response.paused {
+            self.response.status.sync_state = "scheduled".into();
+        }
+    }
+}
+
+fn sync_triggered(id: &str) -> Reply {
+    let message = format!("Sync has been successfully triggered for connection with id '{id}'");
+    let body = json!({ "code": "Success", "message": message });
+    Ok(Json(body).into_response())
+}
+
+async fn sync_connection(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Reply {
+    let req: api::SyncConnectorRequest = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    let connection = state.connection(&id)?;
+    let syncing = connection.response.status.sync_state == "syncing";
+    if !syncing || req.force.unwrap_or(false) {
+        connection.schedule_sync();
+    }
+    sync_triggered(&id)
+}
+
+async fn resync_connection(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Reply {
+    let _: api::ResyncConnectorRequest = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    let connection = state.connection(&id)?;
+    connection.response.status.is_historical_sync = true;
+    connection.schedule_sync();
+    sync_triggered(&id)
+}
+
+async fn resync_tables(State(state): State<Shared>, Path(id): Path<String>, body: Bytes) -> Reply {
+    let req: HashMap<String, Vec<String>> = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    let connection = state.connection(&id)?;
+    let schema = connection
+        .schema
+        .as_ref()
+        .ok_or_else(|| MockError::not_found("Schema config of connection", &id))?;
+    for (s_name, tables) in &req {
+        for t_name in tables {
+            let exists = schema
+                .schemas
+                .get(s_name)
+                .is_some_and(|s| s.tables.contains_key(t_name));
+            if !exists {
+                return Err(MockError::invalid(format!(
+                    "Unknown table '{s_name}.{t_name}'"
+                )));
+            }
+        }
+    }
+    connection.schedule_sync();
+    let body = json!({ "code": "Success", "message": "Re-sync has been triggered successfully" });
+    Ok(Json(body).into_response())
+}
+
+// --- state ---
+
+impl Connection {
+    /// As if the sync read rows up to a newer transaction.
+    fn advance_cursor(&mut self) {
+        if !self.state.is_object() {
+            self.state = json!({});
+        }
+        let cursor = self.state["xmin"]["cursor"].as_u64().unwrap_or(700);
+        self.state["xmin"] = json!({ "cursor": cursor + 10 });
+    }
+}
+
+fn check_state_supported(config: &MockConfig) -> Result<(), MockError> {
+    if config.connection_state {
+        Ok(())
+    } else {
+        Err(MockError {
+            status: StatusCode::METHOD_NOT_ALLOWED,
+            code: "MethodNotAllowed",
+            message: "State is only available for Function and Connector SDK connections".into(),
+        })
+    }
+}
+
+async fn connection_state(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    check_state_supported(&state.config)?;
+    let connection = state.connection(&id)?;
+    success(StatusCode::OK, json!({ "state": connection.state }))
+}
+
+async fn modify_connection_state(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Reply {
+    let req: api::UpdateFunctionOrConnectorSdkState = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    check_state_supported(&state.config)?;
+    let connection = state.connection(&id)?;
+    if !connection.response.paused {
+        return Err(MockError::invalid(format!(
+            "Connection '{id}' must be paused to update its state"
+        )));
+    }
+    connection.state = req.state.unwrap_or(Value::Null);
+    success(StatusCode::OK, json!({ "state": connection.state }))
+}
+
+// --- schemas ---
+
+async fn reload_schema(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    let mut schema = state.config.schema.clone();
+    let connection = state.connection(&id)?;
+    if connection.response.status.setup_state != "connected" {
+        return Err(MockError::invalid(format!(
+            "Connection '{id}' has not passed setup tests yet"
+        )));
+    }
+
+    let prefix = &connection.response.schema;
+    for (name, s) in &mut schema.schemas {
+        s.name_in_destination = format!("{prefix}_{}", name.to_lowercase());
+    }
+
+    // keep settings that were already applied
+    let schema = connection.schema.get_or_insert(schema);
+    success(StatusCode::OK, &*schema)
+}
+
+async fn schema_config(State(state): State<Shared>, Path(id): Path<String>) -> Reply {
+    let mut state = state.lock().unwrap();
+    let connection = state.connection(&id)?;
+    let schema = connection
+        .schema
+        .as_ref()
+        .ok_or_else(|| MockError::not_found("Schema config of connection", &id))?;
+    success(StatusCode::OK, schema)
+}
+
+async fn modify_schema_config(
+    State(state): State<Shared>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Reply {
+    let req: api::StandardConfigUpdateRequest = parse(&body)?;
+
+    let mut state = state.lock().unwrap();
+    let connection = state.connection(&id)?;
+    let schema = connection
+        .schema
+        .as_mut()
+        .ok_or_else(|| MockError::not_found("Schema config of connection", &id))?;
+
+    if let Some(handling) = req.schema_change_handling {
+        schema.schema_change_handling = handling;
+    }
+    for (s_name, s_req) in req.schemas {
+        let s = schema
+            .schemas
+            .get_mut(&s_name)
+            .ok_or_else(|| MockError::invalid(format!("Unknown schema '{s_name}'")))?;
+        s.enabled = s_req.enabled;
+
+        for (t_name, t_req) in s_req.tables.unwrap_or_default() {
+            let t = s
+                .tables
+                .get_mut(&t_name)
+                .ok_or_else(|| MockError::invalid(format!("Unknown table '{t_name}'")))?;
+            t.enabled = t_req.enabled;
+            if t_req.sync_mode.is_some() {
+                t.sync_mode = t_req.sync_mode;
+            }
+
+            for (c_name, c_req) in t_req.columns.unwrap_or_default() {
+                let c = t
+                    .columns
+                    .get_mut(&c_name)
+                    .ok_or_else(|| MockError::invalid(format!("Unknown column '{c_name}'")))?;
+                c.enabled = c_req.enabled;
+                if let Some(hashed) = c_req.hashed {
+                    c.hashed = hashed;
+                }
+            }
+        }
+    }
+    success(StatusCode::OK, &*schema)
+}
+
+// --- fixtures ---
+
+/// Setup tests that pass, except for the last one, which ends with `last`.
+fn setup_tests(last: api::SetupTestResultResponseStatus) -> Vec<api::SetupTestResultResponse> {
+    let mut tests: Vec<_> = [
+        "Connecting to host",
+        "Validating certificate",
+        "Connecting to database",
+    ]
+    .into_iter()
+    .map(|title| api::SetupTestResultResponse {
+        title: title.into(),
+        status: api::SetupTestResultResponseStatus::Passed,
+        message: Some(String::new()),
+        details: None,
+    })
+    .collect();
+
+    let last_test = tests.last_mut().unwrap();
+    last_test.status = last;
+    if last != api::SetupTestResultResponseStatus::Passed {
+        last_test.message = Some(format!("Injected {last:?} result"));
+    }
+    tests
+}
+
+fn is_broken(tests: &[api::SetupTestResultResponse]) -> bool {
+    tests.iter().any(|t| {
+        matches!(
+            t.status,
+            api::SetupTestResultResponseStatus::Failed
+                | api::SetupTestResultResponseStatus::JobFailed
+        )
+    })
+}
+
+/// A small part of the schema of the `basic` scenario, as Fivetran sees it
+/// over the Gel SQL adapter.
+fn default_schema() -> api::StandardConfigResponse {
+    const TABLES: &[(&str, &[&str])] = &[
+        ("Genre", &["id", "__type__", "name"]),
+        (
+            "Person",
+            &["id", "__type__", "first_name", "last_name", "username"],
+        ),
+    ];
+
+    let tables = TABLES
+        .iter()
+        .map(|(t_name, columns)| {
+            let columns = columns
+                .iter()
+                .map(|c_name| {
+                    let c = api::ColumnConfigResponse {
+                        enabled: true,
+                        hashed: false,
+                        is_primary_key: Some(*c_name == "id"),
+                        name_in_destination: c_name.to_lowercase(),
+                        enabled_patch_settings: api::ColumnEnabledPatchSettings {
+                            allowed: *c_name != "id",
+                            ..Default::default()
+                        },
+                    };
+                    (c_name.to_string(), c)
+                })
+                .collect();
+            let t = api::TableConfigResponse {
+                enabled: true,
+                name_in_destination: t_name.to_lowercase(),
+                columns,
+                enabled_patch_settings: api::TableEnabledPatchSettings {
+                    allowed: true,
+                    ..Default::default()
+                },
+                supports_columns_config: Some(true),
+                sync_mode: None,
+            };
+            (t_name.to_string(), t)
+        })
+        .collect();
+
+    let public = api::SchemaConfigResponse {
+        enabled: true,
+        name_in_destination: "public".into(),
+        tables,
+    };
+    api::StandardConfigResponse {
+        enable_new_by_default: Some(true),
+        schema_change_handling: api::StandardConfigResponseSchemaChangeHandling::AllowAll,
+        schemas: HashMap::from([("public".to_string(), public)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a request to `mock` and returns the status and the JSON body.
+    async fn call(
+        mock: &MockServer,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut req = reqwest::Client::new()
+            .request(method, format!("{}{}", mock.url(), &path[1..]))
+            .header(header::AUTHORIZATION, "Basic mock");
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        let res = req.send().await.unwrap();
+        let status = StatusCode::from_u16(res.status().as_u16()).unwrap();
+        (status, res.json().await.unwrap())
+    }
+
+    async fn get(mock: &MockServer, path: &str) -> (StatusCode, Value) {
+        call(mock, reqwest::Method::GET, path, None).await
+    }
+
+    /// Creates a group with a paused connection and returns its id.
+    async fn paused_connection(mock: &MockServer) -> String {
+        let (_, group) = call(
+            mock,
+            reqwest::Method::POST,
+            "/v1/groups",
+            Some(json!({ "name": "gel_test_mock" })),
+        )
+        .await;
+        let (status, connection) = call(
+            mock,
+            reqwest::Method::POST,
+            "/v1/connections",
+            Some(json!({
+                "group_id": group["data"]["id"],
+                "service": "postgres",
+                "paused": true,
+                "config": { "schema_prefix": "gel" },
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        connection["data"]["id"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn lists_are_paged() {
+        let mock = MockServer::start(MockConfig {
+            stale_groups: 5,
+            max_page_size: 2,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut path = "/v1/groups?limit=10".to_string();
+        let mut sizes = Vec::new();
+        loop {
+            let (status, body) = get(&mock, &path).await;
+            assert_eq!(status, StatusCode::OK);
+            sizes.push(body["data"]["items"].as_array().unwrap().len());
+            match body["data"]["next_cursor"].as_str() {
+                Some(cursor) => path = format!("/v1/groups?limit=10&cursor={cursor}"),
+                None => break,
+            }
+        }
+        assert_eq!(sizes, [2, 2, 1]);
+
+        let (status, _) = get(&mock, "/v1/groups?cursor=nope").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn connection_syncs_after_unpausing() {
+        let mock = MockServer::start(MockConfig::default()).await.unwrap();
+        let id = paused_connection(&mock).await;
+        let path = format!("/v1/connections/{id}");
+        let status = async || {
+            let (_, body) = get(&mock, &path).await;
+            let data = &body["data"];
+            (
+                data["status"]["setup_state"].as_str().unwrap().to_string(),
+                data["status"]["sync_state"].as_str().unwrap().to_string(),
+                data["succeeded_at"].is_string(),
+            )
+        };
+
+        // setup_polls = 1
+        assert_eq!(status().await, ("connected".into(), "paused".into(), false));
+        assert_eq!(status().await, ("connected".into(), "paused".into(), false));
+
+        let (code, _) = call(
+            &mock,
+            reqwest::Method::PATCH,
+            &path,
+            Some(json!({ "paused": false })),
+        )
+        .await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(
+            status().await,
+            ("connected".into(), "syncing".into(), false)
+        );
+        // sync_polls = 2
+        assert_eq!(
+            status().await,
+            ("connected".into(), "syncing".into(), false)
+        );
+        assert_eq!(
+            status().await,
+            ("connected".into(), "scheduled".into(), true)
+        );
+        assert_eq!(
+            status().await,
+            ("connected".into(), "scheduled".into(), true)
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_sync_raises_an_alert() {
+        let mock = MockServer::start(MockConfig {
+            sync_fails: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let id = paused_connection(&mock).await;
+        let path = format!("/v1/connections/{id}");
+        get(&mock, &path).await;
+        call(
+            &mock,
+            reqwest::Method::PATCH,
+            &path,
+            Some(json!({ "paused": false })),
+        )
+        .await;
+        for _ in 0..3 {
+            get(&mock, &path).await;
+        }
+        let (_, body) = get(&mock, &path).await;
+        assert!(body["data"]["failed_at"].is_string());
+        assert_eq!(
+            body["data"]["status"]["tasks"][0]["code"],
+            "mock_sync_failed"
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_objects_are_not_found() {
+        let mock = MockServer::start(MockConfig::default()).await.unwrap();
+        for path in [
+            "/v1/groups/nope",
+            "/v1/destinations/nope",
+            "/v1/connections/nope",
+            "/v1/connections/nope/schemas",
+            "/v1/unknown",
+        ] {
+            let (status, body) = get(&mock, path).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{path}");
+            assert_eq!(body["code"], "NotFound_Entity", "{path}");
+        }
+        let (status, _) = call(&mock, reqwest::Method::DELETE, "/v1/groups/nope", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn requests_need_authorization() {
+        let mock = MockServer::start(MockConfig::default()).await.unwrap();
+        let res = reqwest::get(format!("{}v1/groups", mock.url()))
+            .await
+            .unwrap();
+        assert_eq!(res.status().as_u16(), 401);
+    }
+
+    #[tokio::test]
+    async fn state_is_not_allowed_by_default() {
+        let mock = MockServer::start(MockConfig::default()).await.unwrap();
+        let id = paused_connection(&mock).await;
+        let (status, body) = get(&mock, &format!("/v1/connections/{id}/state")).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(body["code"], "MethodNotAllowed");
+    }
+
+    #[tokio::test]
+    async fn sync_moves_the_cursor_in_the_state() {
+        let mock = MockServer::start(MockConfig {
+            connection_state: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let id = paused_connection(&mock).await;
+        let path = format!("/v1/connections/{id}");
+        let state_path = format!("{path}/state");
+        let (status, body) = get(&mock, &state_path).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["state"], json!({}));
+
+        get(&mock, &path).await;
+        call(
+            &mock,
+            reqwest::Method::PATCH,
+            &path,
+            Some(json!({ "paused": false })),
+        )
+        .await;
+        for _ in 0..3 {
+            get(&mock, &path).await;
+        }
+        let (_, body) = get(&mock, &state_path).await;
+        assert_eq!(body["data"]["state"]["xmin"]["cursor"], 710);
+
+        // the state can only be changed while paused
+        let rewind = Some(json!({ "state": { "xmin": { "cursor": 700 } } }));
+        let (status, _) = call(&mock, reqwest::Method::PATCH, &state_path, rewind.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        call(
+            &mock,
+            reqwest::Method::PATCH,
+            &path,
+            Some(json!({ "paused": true })),
+        )
+        .await;
+        let (status, body) = call(&mock, reqwest::Method::PATCH, &state_path, rewind).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["state"]["xmin"]["cursor"], 700);
+    }
+}
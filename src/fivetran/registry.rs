@@ -0,0 +1,175 @@
+//! Bookkeeping of Fivetran objects created during a run, so they get deleted
+//! however the run ends.
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+use futures::FutureExt;
+
+use super::{Client, FivetranError, api};
+use crate::report::Report;
+
+/// A Fivetran object that we created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Group(String),
+    Destination(String),
+    Connection(String),
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::Group(id) => write!(f, "group {id}"),
+            Resource::Destination(id) => write!(f, "destination {id}"),
+            Resource::Connection(id) => write!(f, "connection {id}"),
+        }
+    }
+}
+
+/// Created objects, in order of creation.
+#[derive(Clone, Default)]
+pub struct Registry {
+    resources: Arc<Mutex<Vec<Resource>>>,
+}
+
+/// Outcome of [Registry::teardown].
+#[derive(Debug, Default)]
+pub struct Teardown {
+    pub deleted: Vec<Resource>,
+    pub failed: Vec<(Resource, FivetranError)>,
+}
+
+impl Registry {
+    /// Records an object right after it was created.
+    pub fn add(&self, resource: Resource) {
+        log::debug!("created {resource}");
+        self.resources.lock().unwrap().push(resource);
+    }
+
+    /// Stops tracking all objects, so they outlive the run.
+    pub fn forget(&self) -> Vec<Resource> {
+        std::mem::take(&mut *self.resources.lock().unwrap())
+    }
+
+    /// Deletes all objects, newest first. Objects that are already gone
+    /// count as deleted, the rest stays registered.
+    pub async fn teardown(&self, client: &Client) -> Teardown {
+        let resources = self.forget();
+        let mut teardown = Teardown::default();
+        for resource in resources.into_iter().rev() {
+            let res = match &resource {
+                Resource::Group(id) => api::delete_group(client, id).await,
+                Resource::Destination(id) => api::delete_destination(client, id).await,
+                Resource::Connection(id) => api::delete_connection(client, id).await,
+            };
+            match res {
+                Ok(()) => teardown.deleted.push(resource),
+                Err(e) if e.is_not_found() => teardown.deleted.push(resource),
+                Err(e) => teardown.failed.push((resource, e)),
+            }
+        }
+        self.resources
+            .lock()
+            .unwrap()
+            .extend(teardown.failed.iter().rev().map(|(r, _)| r.clone()));
+        teardown
+    }
+
+    /// Runs `run`, then deletes all registered objects in a `cleanup` phase
+    /// of the report. The objects are deleted also when `run` fails or
+    /// panics, or when the process gets SIGINT or SIGTERM.
+    ///
+    /// Fails with the error of `run` or, if it succeeded, with objects that
+    /// could not be deleted.
+    pub async fn run_and_teardown<T>(
+        &self,
+        client: &Client,
+        report: &Report,
+        run: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let res = tokio::select! {
+            // `run` goes first, so it can handle Ctrl-C itself
+            biased;
+            res = AssertUnwindSafe(run).catch_unwind() => match res {
+                Ok(res) => res,
+                Err(panic) => Err(anyhow::anyhow!("panicked: {}", panic_message(&panic))),
+            },
+            signal = shutdown_signal() => Err(anyhow::anyhow!("interrupted by {signal}")),
+        };
+
+        let teardown = report
+            .infrastructure("cleanup", async {
+                let teardown = self.teardown(client).await;
+                teardown.log();
+                for resource in &teardown.deleted {
+                    report.object(resource);
+                }
+                teardown.into_result()
+            })
+            .await;
+        let value = res?;
+        teardown?;
+        Ok(value)
+    }
+}
+
+impl Teardown {
+    pub fn log(&self) {
+        if self.deleted.is_empty() && self.failed.is_empty() {
+            return;
+        }
+        log::info!(
+            "teardown: deleted {} objects, failed to delete {}",
+            self.deleted.len(),
+            self.failed.len()
+        );
+        for resource in &self.deleted {
+            log::info!("  deleted {resource}");
+        }
+        for (resource, e) in &self.failed {
+            log::error!("  could not delete {resource}: {e}");
+        }
+    }
+
+    pub fn into_result(self) -> anyhow::Result<()> {
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        let failed: Vec<_> = self.failed.iter().map(|(r, _)| r.to_string()).collect();
+        anyhow::bail!("could not delete {}", failed.join(", "))
+    }
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Resolves to the name of the first termination signal received.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
@@ -0,0 +1,215 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// When and how often failed requests to the Fivetran API are repeated.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+
+    /// Delay before the first retry. Doubles with each following attempt.
+    pub base_delay: Duration,
+
+    /// Upper bound for the exponential backoff.
+    pub max_delay: Duration,
+
+    /// If the server asks us to wait longer than this (via `Retry-After`),
+    /// we give up instead.
+    pub max_retry_after: Duration,
+
+    /// Retry POST and PATCH requests even if they were not marked as
+    /// idempotent with [super::Request::idempotent].
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(300),
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a request that got `status`, or no response for `None`, can
+    /// be repeated. Rate limited requests were not processed, so they
+    /// always can.
+    pub fn can_retry(
+        &self,
+        method: &reqwest::Method,
+        idempotent: bool,
+        status: Option<reqwest::StatusCode>,
+    ) -> bool {
+        status == Some(reqwest::StatusCode::TOO_MANY_REQUESTS)
+            || self.can_retry_method(method, idempotent)
+    }
+
+    pub fn can_retry_method(&self, method: &reqwest::Method, idempotent: bool) -> bool {
+        idempotent
+            || self.retry_non_idempotent
+            || matches!(
+                *method,
+                reqwest::Method::GET | reqwest::Method::HEAD | reqwest::Method::DELETE
+            )
+    }
+
+    /// Delay before attempt number `attempt + 1`, or `None` if we should stop.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_retry_after).then_some(retry_after);
+        }
+
+        // "equal jitter": half of the delay is fixed, the other half random
+        let exp = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        let half = exp / 2;
+        let jitter = rand::rng().random_range(Duration::ZERO..=half);
+        Some(half + jitter)
+    }
+}
+
+pub fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    matches!(
+        status,
+        reqwest::StatusCode::TOO_MANY_REQUESTS
+            | reqwest::StatusCode::INTERNAL_SERVER_ERROR
+            | reqwest::StatusCode::BAD_GATEWAY
+            | reqwest::StatusCode::SERVICE_UNAVAILABLE
+            | reqwest::StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Transient transport errors. Others, like failing to build or encode the
+/// request, fail the same way when repeated.
+pub fn is_retryable_error(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or
+/// an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = at.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 6,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_delay() {
+        let policy = policy();
+        for (attempt, full) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000)] {
+            let full = Duration::from_millis(full);
+            for _ in 0..20 {
+                let delay = policy.delay(attempt, None).unwrap();
+                assert!(
+                    full / 2 <= delay && delay <= full,
+                    "attempt {attempt}: {delay:?} not within {full:?}/2..={full:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_stops_after_max_attempts() {
+        let policy = policy();
+        assert_eq!(policy.delay(6, None), None);
+        assert_eq!(policy.delay(6, Some(Duration::from_secs(1))), None);
+
+        let never = RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        assert_eq!(never.delay(1, None), None);
+    }
+
+    #[test]
+    fn retry_after_replaces_backoff() {
+        let policy = policy();
+        let wait = Duration::from_secs(120);
+        assert_eq!(policy.delay(1, Some(wait)), Some(wait));
+
+        let too_long = policy.max_retry_after + Duration::from_secs(1);
+        assert_eq!(policy.delay(1, Some(too_long)), None);
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn retry_after_as_http_date() {
+        let at = chrono::Utc::now() + chrono::TimeDelta::seconds(60);
+        let header = at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let wait = parse_retry_after(&header).unwrap();
+        assert!(
+            Duration::from_secs(55) <= wait && wait <= Duration::from_secs(60),
+            "{header}: {wait:?}"
+        );
+
+        // dates in the past mean no waiting
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn non_idempotent_requests_are_not_retried() {
+        let policy = RetryPolicy::default();
+        assert!(!policy.can_retry_method(&reqwest::Method::POST, false));
+        assert!(!policy.can_retry_method(&reqwest::Method::PATCH, false));
+        assert!(policy.can_retry_method(&reqwest::Method::POST, true));
+        assert!(policy.can_retry_method(&reqwest::Method::GET, false));
+        assert!(policy.can_retry_method(&reqwest::Method::DELETE, false));
+
+        let policy = RetryPolicy {
+            retry_non_idempotent: true,
+            ..Default::default()
+        };
+        assert!(policy.can_retry_method(&reqwest::Method::POST, false));
+    }
+
+    #[test]
+    fn rate_limited_requests_are_always_retried() {
+        let policy = RetryPolicy::default();
+        let post = reqwest::Method::POST;
+        let status = |code| Some(reqwest::StatusCode::from_u16(code).unwrap());
+        assert!(policy.can_retry(&post, false, status(429)));
+        assert!(policy.can_retry(&reqwest::Method::PATCH, false, status(429)));
+        assert!(!policy.can_retry(&post, false, status(503)));
+        assert!(!policy.can_retry(&post, false, None));
+        assert!(policy.can_retry(&post, true, status(503)));
+    }
+}
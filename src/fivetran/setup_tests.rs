@@ -0,0 +1,80 @@
+use super::api::{SetupTestResultResponse, SetupTestResultResponseStatus};
+use crate::report::Report;
+
+/// Some setup tests of a destination or connection did not pass.
+#[derive(Debug, thiserror::Error)]
+#[error("setup tests of {object} failed:{}", list_tests(.failed))]
+pub struct SetupTestsFailed {
+    /// e.g. `destination abc_xyz`
+    pub object: String,
+    pub failed: Vec<SetupTestResultResponse>,
+}
+
+fn list_tests(tests: &[SetupTestResultResponse]) -> String {
+    tests
+        .iter()
+        .map(|t| format!("\n  {}: {}", t.title, t.message.as_deref().unwrap_or("")))
+        .collect()
+}
+
+fn is_failed(test: &SetupTestResultResponse) -> bool {
+    matches!(
+        test.status,
+        SetupTestResultResponseStatus::Failed | SetupTestResultResponseStatus::JobFailed
+    )
+}
+
+/// Fails if any of the tests failed, without logging or reporting them.
+/// For checks while the tests might still be running.
+pub fn check_failed(
+    object: &str,
+    tests: Option<&[SetupTestResultResponse]>,
+) -> Result<(), SetupTestsFailed> {
+    let failed: Vec<_> = tests
+        .unwrap_or_default()
+        .iter()
+        .filter(|t| is_failed(t))
+        .cloned()
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(SetupTestsFailed {
+            object: object.to_string(),
+            failed,
+        })
+    }
+}
+
+/// Logs results of setup tests, puts warnings into the report and fails if
+/// any of the tests failed.
+pub fn check(
+    object: &str,
+    tests: Option<&[SetupTestResultResponse]>,
+    report: &Report,
+) -> Result<(), SetupTestsFailed> {
+    let Some(tests) = tests else {
+        log::warn!("{object}: no setup test results");
+        return Ok(());
+    };
+
+    log::info!("{object}: setup tests");
+    for test in tests {
+        let message = test.message.as_deref().unwrap_or("");
+        match test.status {
+            SetupTestResultResponseStatus::Passed | SetupTestResultResponseStatus::Skipped => {
+                log::info!("  {:?}: {}", test.status, test.title);
+            }
+            SetupTestResultResponseStatus::Warning => {
+                report.warn(&format!("{object} setup tests"), &test.title, message);
+            }
+            SetupTestResultResponseStatus::Failed | SetupTestResultResponseStatus::JobFailed => {
+                log::error!("  {:?}: {}: {message}", test.status, test.title);
+            }
+        }
+        if let Some(details) = &test.details {
+            log::debug!("    details: {details}");
+        }
+    }
+    check_failed(object, Some(tests))
+}
@@ -1,138 +1,1048 @@
 mod fivetran;
 mod postgres;
+mod report;
+mod scenario;
+mod servers;
+mod tunnel;
 
+use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::str::FromStr;
-use std::{env, path};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::Parser;
+
+use report::{Report, ReportArgs};
+use scenario::{Evolution, Evolutions, Scenario, SelectArgs, Snapshot};
+use servers::{ServerArgs, Servers};
+use tunnel::Tunnel;
+
+/// Tests syncing data from Gel to Postgres with Fivetran.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    fivetran: fivetran::ClientOptions,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Sets up servers, tunnels and a sync, validates synced data and cleans
+    /// up, for each scenario.
+    ///
+    /// With FIVETRAN_MOCK set, runs only the Fivetran part against a local
+    /// emulator of the Fivetran API. With FIVETRAN_REPLAY=<cassette>, repeats
+    /// the Fivetran part of a run recorded with FIVETRAN_RECORD=<cassette>.
+    Run {
+        #[command(flatten)]
+        servers: ServerArgs,
+        #[command(flatten)]
+        wait: WaitArgs,
+        #[command(flatten)]
+        scenario: ScenarioArgs,
+        #[command(flatten)]
+        cleanup: CleanupOldArgs,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+
+    /// Starts servers and tunnels and creates Fivetran objects, without syncing.
+    ///
+    /// Prints how to reach everything and keeps it alive until Ctrl-C.
+    Setup {
+        #[command(flatten)]
+        servers: ServerArgs,
+        #[command(flatten)]
+        select: SelectArgs,
+        /// How the connection detects changes in Gel
+        #[arg(long, value_enum, value_name = "METHOD", default_value = "xmin")]
+        update_method: fivetran::UpdateMethod,
+        #[command(flatten)]
+        wait: WaitArgs,
+        #[command(flatten)]
+        hold: HoldArgs,
+    },
+
+    /// Syncs the connection of an existing setup and waits for the sync.
+    Sync {
+        #[command(flatten)]
+        setup: SetupArgs,
+        #[command(flatten)]
+        wait: WaitArgs,
+    },
+
+    /// Validates synced data in a running Postgres.
+    Validate {
+        /// Postgres that Fivetran synced into
+        #[arg(long, value_name = "HOST:PORT")]
+        postgres_addr: SocketAddr,
+        #[command(flatten)]
+        select: SelectArgs,
+        #[command(flatten)]
+        checks: CheckArgs,
+    },
+
+    /// Deletes the Fivetran objects of an existing setup.
+    Cleanup {
+        #[command(flatten)]
+        setup: SetupArgs,
+    },
+
+    /// Deletes Fivetran objects left behind by earlier runs.
+    ///
+    /// Only deletes groups named with the group prefix, or like
+    /// test_2024_01_31T12_00_00 by runners from before the prefix, together
+    /// with their destinations and connections.
+    CleanupOld {
+        #[command(flatten)]
+        cleanup: CleanupOldArgs,
+        /// Only list what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Sets up servers, tunnels and a sync like `run`, then waits for Ctrl-C.
+    ///
+    /// Keeps everything alive after the first sync, for debugging by hand.
+    Hold {
+        #[command(flatten)]
+        servers: ServerArgs,
+        #[command(flatten)]
+        select: SelectArgs,
+        /// How the connection detects changes in Gel
+        #[arg(long, value_enum, value_name = "METHOD", default_value = "xmin")]
+        update_method: fivetran::UpdateMethod,
+        #[command(flatten)]
+        wait: WaitArgs,
+        #[command(flatten)]
+        hold: HoldArgs,
+    },
+}
+
+/// Waiting for Fivetran.
+#[derive(clap::Args)]
+struct WaitArgs {
+    /// Seconds between checks of the connection status
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    poll_interval: u64,
+
+    /// Give up waiting for connection setup or a sync after this many seconds
+    #[arg(long, value_name = "SECS")]
+    wait_timeout: Option<u64>,
+}
+
+impl WaitArgs {
+    fn apply(&self, client: fivetran::Client) -> fivetran::Client {
+        client
+            .with_poll_interval(Duration::from_secs(self.poll_interval))
+            .with_wait_timeout(self.wait_timeout.map(Duration::from_secs))
+    }
+}
+
+/// Fivetran objects of an earlier `setup`.
+#[derive(clap::Args)]
+struct SetupArgs {
+    /// Group printed by `setup`
+    #[arg(long, value_name = "ID")]
+    group_id: String,
+}
+
+/// Deleting Fivetran objects left behind by earlier runs.
+#[derive(clap::Args)]
+struct CleanupOldArgs {
+    /// Delete groups created more than this many minutes ago
+    #[arg(long, value_name = "MINUTES", default_value_t = 15)]
+    cleanup_max_age: u64,
+}
+
+impl CleanupOldArgs {
+    fn options(&self, dry_run: bool) -> fivetran::CleanupOld {
+        fivetran::CleanupOld {
+            max_age: Duration::from_secs(self.cleanup_max_age * 60),
+            dry_run,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct HoldArgs {
+    /// Keep the Fivetran objects after Ctrl-C
+    #[arg(long)]
+    keep: bool,
+}
+
+/// Which scenarios and checks to run.
+#[derive(clap::Args)]
+struct ScenarioArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    #[command(flatten)]
+    checks: CheckArgs,
+
+    /// After validating the initial sync, re-sync this table and validate
+    /// it again. Can be repeated.
+    #[arg(long = "resync-table", value_name = "SCHEMA.TABLE")]
+    resync_tables: Vec<fivetran::SourceTable>,
+
+    /// After validating the initial sync, re-sync the whole connection and
+    /// validate all checks again
+    #[arg(long)]
+    resync_connection: bool,
+
+    /// Skip applying mutations and checking the sync that picks them up
+    #[arg(long)]
+    skip_mutation: bool,
+
+    /// Skip changing Gel data to check incremental syncs
+    #[arg(long)]
+    skip_incremental: bool,
+
+    /// Skip comparing all replicated tables and their deletes with their
+    /// source in Gel
+    #[arg(long)]
+    skip_parity: bool,
+
+    /// Expected destination column type of each Gel scalar type
+    #[arg(long, value_name = "PATH", default_value = "type_mapping.toml")]
+    type_mapping: PathBuf,
+
+    /// Skip checking destination column types against the type mapping
+    #[arg(long)]
+    skip_column_types: bool,
+
+    /// Skip migrating Gel and checking how connections handle the new schema
+    #[arg(long)]
+    skip_evolution: bool,
+
+    /// Run each scenario with this update method of the connection. Can be
+    /// repeated. Methods that Gel does not support yet are expected to fail.
+    #[arg(
+        long = "update-method",
+        value_enum,
+        value_name = "METHOD",
+        default_value = "xmin"
+    )]
+    update_methods: Vec<fivetran::UpdateMethod>,
+}
+
+#[derive(clap::Args)]
+struct CheckArgs {
+    /// Only run checks that read this destination table, e.g.
+    /// gel_public.movie. Can be repeated.
+    #[arg(long = "table", value_name = "SCHEMA.TABLE")]
+    tables: Vec<String>,
+
+    /// Rewrite the .expected files of checks from what the destination
+    /// returns, instead of failing on a difference
+    #[arg(long)]
+    bless: bool,
+}
+
+impl CheckArgs {
+    async fn validate(
+        &self,
+        postgres_addr: SocketAddr,
+        scenario: &Scenario,
+        report: &Report,
+    ) -> anyhow::Result<()> {
+        let checks = &scenario.config.checks;
+        if self.tables.is_empty() {
+            postgres::validate_data(postgres_addr, checks, self.bless, report).await
+        } else {
+            postgres::validate_tables(postgres_addr, checks, &self.tables, self.bless, report).await
+        }
+    }
+}
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
+    let args = Args::parse();
 
-    log::info!("cleanup_old");
-    fivetran::cleanup_old().await?;
+    // commands that do not talk to Fivetran, or not to the real one
+    match &args.command {
+        Command::Run {
+            wait,
+            scenario,
+            cleanup,
+            report,
+            ..
+        } => {
+            if env::var_os("FIVETRAN_MOCK").is_some() {
+                return with_report(report, async |r| run_mock(wait, scenario, cleanup, r).await)
+                    .await;
+            }
+            if let Ok(cassette) = env::var("FIVETRAN_REPLAY") {
+                return with_report(report, async |r| {
+                    run_replay(scenario, cleanup, cassette, r).await
+                })
+                .await;
+            }
+        }
+        Command::Validate {
+            postgres_addr,
+            select,
+            checks,
+        } => {
+            let scenario = select.load_one()?;
+            checks
+                .validate(*postgres_addr, &scenario, &Report::new())
+                .await?;
+            log::info!("validation passed");
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config = fivetran::ClientConfig::load(args.fivetran)?;
+    let client = with_recording(fivetran::Client::from_config(&config)?);
+
+    match args.command {
+        Command::Run {
+            servers,
+            wait,
+            scenario,
+            cleanup,
+            report,
+        } => {
+            let client = wait.apply(client);
+            let tunnel = servers.tunnel.build()?;
+            with_report(&report, async |r| {
+                run(&client, &servers, &tunnel, &scenario, &cleanup, r).await
+            })
+            .await
+        }
+        Command::Setup {
+            servers,
+            select,
+            update_method,
+            wait,
+            hold,
+        } => {
+            let client = wait.apply(client);
+            setup_and_hold(&client, &servers, &select, update_method, &hold, false).await
+        }
+        Command::Sync { setup, wait } => {
+            let client = wait.apply(client);
+            let objects = fivetran::CreatedObjects::find(&client, &setup.group_id).await?;
+            fivetran::sync_and_wait(&client, &objects).await?;
+            log::info!("sync succeeded");
+            Ok(())
+        }
+        Command::Validate { .. } => unreachable!(),
+        Command::Cleanup { setup } => {
+            let objects = fivetran::CreatedObjects::find(&client, &setup.group_id).await?;
+            fivetran::cleanup(&client, &objects).await
+        }
+        Command::CleanupOld { cleanup, dry_run } => {
+            let cleanup = fivetran::cleanup_old(&client, &cleanup.options(dry_run)).await?;
+            cleanup.log();
+            cleanup.into_result()
+        }
+        Command::Hold {
+            servers,
+            select,
+            update_method,
+            wait,
+            hold,
+        } => {
+            let client = wait.apply(client);
+            setup_and_hold(&client, &servers, &select, update_method, &hold, true).await
+        }
+    }
+}
+
+/// Runs `run` with a new report, then writes the report, also when the run
+/// failed.
+async fn with_report(
+    args: &ReportArgs,
+    run: impl AsyncFnOnce(&Report) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let report = Report::new();
+    let res = run(&report).await;
+    report.log_summary();
+    let written = args.write(&report, &res);
+    res?;
+    written
+}
+
+/// Runs the selected scenarios one after another, each with its own
+/// servers and Fivetran objects. Stops at the first one that fails.
+async fn run(
+    client: &fivetran::Client,
+    servers: &ServerArgs,
+    tunnel: &Arc<dyn Tunnel>,
+    args: &ScenarioArgs,
+    cleanup: &CleanupOldArgs,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let scenarios = args.select.load()?;
+
+    cleanup_old(client, cleanup, report).await?;
 
-    let (postgres, gel_server) = tokio::join!(start_postgres(), start_gel_server(),);
-    let gel_addr_local = SocketAddr::new(
-        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-        gel_server.info.port,
+    for scenario in &scenarios {
+        for &method in &args.update_methods {
+            log::info!("running scenario {} with {method}", scenario.name);
+            let run = run_scenario(client, servers, tunnel, scenario, method, args, report);
+            report
+                .scenario(&scenario.name, method, method.expect_unsupported(), run)
+                .await
+                .with_context(|| format!("scenario {} with {method}", scenario.name))?;
+        }
+    }
+    log::info!("sync tests passed");
+    Ok(())
+}
+
+/// Sets up a sync between local servers, validates it and cleans up.
+async fn run_scenario(
+    client: &fivetran::Client,
+    servers: &ServerArgs,
+    tunnel: &Arc<dyn Tunnel>,
+    scenario: &Scenario,
+    update_method: fivetran::UpdateMethod,
+    args: &ScenarioArgs,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let registry = fivetran::Registry::default();
+    registry
+        .run_and_teardown(client, report, async {
+            let servers = Servers::start(servers, tunnel, scenario, update_method, report).await?;
+
+            // run tests
+            log::info!("setting up fivetran sync");
+            let objects = fivetran::setup_sync(
+                client,
+                &registry,
+                report,
+                &servers.postgres_endpoint,
+                &servers.source(scenario),
+            )
+            .await?;
+
+            validate(client, &objects, report, &servers, scenario, args).await?;
+
+            if let Some(evolution) = &scenario.config.evolution
+                && !args.skip_evolution
+            {
+                log::info!("checking schema evolution");
+                let postgres_addr = servers.postgres.addr();
+                check_evolution(
+                    client,
+                    &registry,
+                    report,
+                    &servers.postgres_endpoint,
+                    &servers.source(scenario),
+                    evolution,
+                    || servers.gel.query_file(&scenario.migrate_file()),
+                    async |handling, prefix: &str, evolution: &Evolution, reloaded: String| {
+                        let reload = &evolution.reload;
+                        validate_reload(handling, reload, reloaded, args.checks.bless, report)
+                            .await?;
+                        let checks = evolution.checks(prefix);
+                        postgres::validate_data(postgres_addr, &checks, args.checks.bless, report)
+                            .await
+                    },
+                )
+                .await?;
+            }
+            Ok(())
+        })
+        .await
+}
+
+/// Starts servers and sets up Fivetran objects, optionally with a first
+/// sync, and keeps them until Ctrl-C.
+async fn setup_and_hold(
+    client: &fivetran::Client,
+    servers: &ServerArgs,
+    select: &SelectArgs,
+    update_method: fivetran::UpdateMethod,
+    hold: &HoldArgs,
+    sync: bool,
+) -> anyhow::Result<()> {
+    let scenario = select.load_one()?;
+    let tunnel = servers.tunnel.build()?;
+    let registry = fivetran::Registry::default();
+    let report = Report::new();
+    registry
+        .run_and_teardown(client, &report, async {
+            let servers =
+                Servers::start(servers, &tunnel, &scenario, update_method, &report).await?;
+            let (pg, gel) = (&servers.postgres_endpoint, &servers.source(&scenario));
+            let objects = if sync {
+                fivetran::setup_sync(client, &registry, &report, pg, gel).await?
+            } else {
+                fivetran::setup(client, &registry, &report, pg, gel).await?
+            };
+            report.log_summary();
+            print_setup(&servers, &scenario, &objects);
+
+            if hold.keep {
+                registry.forget();
+                log::info!("keeping fivetran group {}", objects.group_id());
+            }
+            log::info!("holding until ctrl-c");
+            tokio::signal::ctrl_c().await?;
+            Ok(())
+        })
+        .await
+}
+
+fn print_setup(servers: &Servers, scenario: &Scenario, objects: &fivetran::CreatedObjects) {
+    println!(
+        "gel:         {} (for fivetran {})",
+        servers.gel.addr(),
+        servers.gel_endpoint
+    );
+    println!(
+        "postgres:    {} (for fivetran {})",
+        servers.postgres.addr(),
+        servers.postgres_endpoint
     );
-    // let postgres = start_postgres().await;
-    // let gel_addr_local = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 5656);
+    println!("group:       {}", objects.group_id());
+    println!("destination: {}", objects.destination_id());
+    println!("connection:  {}", objects.connection_id());
+    println!();
+    println!("runner sync --group-id {}", objects.group_id());
+    println!(
+        "runner validate --postgres-addr {} --scenario {}",
+        servers.postgres.addr(),
+        scenario.name
+    );
+}
+
+/// Runs the Fivetran part of the tests against a local emulator of the
+/// Fivetran API, without databases or bore. Synced data is not validated.
+async fn run_mock(
+    wait: &WaitArgs,
+    scenario: &ScenarioArgs,
+    cleanup: &CleanupOldArgs,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let config = fivetran::mock::MockConfig::from_env()?;
+    let foreign = config.foreign_groups;
+    let mock = fivetran::mock::MockServer::start(config).await?;
+    let config = fivetran::ClientConfig {
+        base_url: mock.url(),
+        ..fivetran::ClientConfig::new(offline_auth())
+    };
+    let client = fivetran::Client::from_config(&config)?
+        .with_poll_interval(Duration::from_millis(100))
+        .with_wait_timeout(wait.wait_timeout.map(Duration::from_secs));
+    let client = with_recording(client);
+
+    run_fivetran_only(&client, scenario, cleanup, report).await?;
 
-    log::debug!("postgres = {:?}", postgres);
-    log::debug!("gel_server = {:?}", gel_server.info);
+    // each group of someone else has a destination and a connection
+    let (groups, destinations, connections) = mock.object_counts();
+    anyhow::ensure!(
+        groups == foreign && destinations == foreign && connections == foreign,
+        "cleanup left {groups} groups, {destinations} destinations \
+         and {connections} connections, expected {foreign} of each from others"
+    );
+    log::info!("mock run passed");
+    Ok(())
+}
 
-    let postgres_bore = init_bore(postgres.tcp_address).await?;
-    let postgres_addr_pub = get_bore_pub_addr(&postgres_bore)?;
+/// Repeats the Fivetran part of a run recorded with `FIVETRAN_RECORD`,
+/// serving all API responses from the cassette.
+async fn run_replay(
+    scenario: &ScenarioArgs,
+    cleanup: &CleanupOldArgs,
+    cassette: String,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let cassette = fivetran::Cassette::replay(cassette)?;
+    let config = fivetran::ClientConfig::new(offline_auth());
+    let client = fivetran::Client::from_config(&config)?
+        .with_cassette(cassette)
+        .with_poll_interval(Duration::ZERO)
+        .with_retry_policy(fivetran::RetryPolicy {
+            base_delay: Duration::ZERO,
+            ..Default::default()
+        });
 
-    let gel_server_bore = init_bore(gel_addr_local).await?;
-    let gel_addr_pub = get_bore_pub_addr(&gel_server_bore)?;
+    run_fivetran_only(&client, scenario, cleanup, report).await?;
 
-    log::info!("postgres_addr_pub = {postgres_addr_pub:?}");
-    log::info!("gel_addr_pub = {gel_addr_pub:?}");
+    let unused = client.cassette().unwrap().unused();
+    anyhow::ensure!(
+        unused == 0,
+        "{unused} recorded responses were never requested, replay diverged from the recording"
+    );
+    log::info!("replay passed");
+    Ok(())
+}
 
-    // run bores until ctrl-c or timeout
-    tokio::spawn(async {
-        tokio::select! {
-            r = run_bores(postgres_bore, gel_server_bore) => {
-                r.unwrap();
+/// Deletes our Fivetran objects left behind by earlier runs, in a phase of
+/// the report.
+async fn cleanup_old(
+    client: &fivetran::Client,
+    args: &CleanupOldArgs,
+    report: &Report,
+) -> anyhow::Result<()> {
+    log::info!("cleanup_old");
+    report
+        .infrastructure("cleanup old objects", async {
+            let cleanup = fivetran::cleanup_old(client, &args.options(false)).await?;
+            cleanup.log();
+            for group in cleanup.removed() {
+                report.object(format!("group {}", group.id));
             }
-            _ = tokio::signal::ctrl_c() => {},
-            // _ = tokio::time::sleep(tokio::time::Duration::from_secs(1000)) => {}
+            cleanup.into_result()
+        })
+        .await
+}
+
+/// Sets up a sync, re-syncs tables and cleans up for each scenario, without
+/// databases or bore.
+async fn run_fivetran_only(
+    client: &fivetran::Client,
+    args: &ScenarioArgs,
+    cleanup: &CleanupOldArgs,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let scenarios = args.select.load()?;
+
+    cleanup_old(client, cleanup, report).await?;
+
+    // Fivetran is not going to connect to these
+    let unused_addr =
+        fivetran::Endpoint::Direct(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0));
+
+    for scenario in &scenarios {
+        for &method in &args.update_methods {
+            log::info!(
+                "setting up fivetran sync for scenario {} with {method}",
+                scenario.name
+            );
+            let source = fivetran::Source {
+                endpoint: &unused_addr,
+                database: "main",
+                skip: &scenario.config.skip,
+                update_method: method,
+                schema_prefix: "gel",
+                schema_change_handling: fivetran::SchemaChangeHandling::BlockAll,
+            };
+            let registry = fivetran::Registry::default();
+            let run = registry.run_and_teardown(client, report, async {
+                let objects =
+                    fivetran::setup_sync(client, &registry, report, &unused_addr, &source).await?;
+                if !args.resync_tables.is_empty() {
+                    resync(client, &objects, report, &args.resync_tables).await?;
+                }
+                if args.resync_connection {
+                    resync_connection(client, &objects, report).await?;
+                }
+                if !args.skip_mutation && scenario.config.mutation.is_some() {
+                    sync_mutations(client, &objects, report).await?;
+                }
+                if !args.skip_incremental && scenario.config.incremental.is_some() {
+                    // there is no data to change, but the emulator moves the cursor anyway
+                    check_cursor(client, &objects, report, || Ok(()), async || Ok(())).await?;
+                }
+                if let Some(evolution) = &scenario.config.evolution
+                    && !args.skip_evolution
+                {
+                    // there is no schema to change
+                    check_evolution(
+                        client,
+                        &registry,
+                        report,
+                        &unused_addr,
+                        &source,
+                        evolution,
+                        || Ok(()),
+                        async |_, _: &str, _: &Evolution, _| Ok(()),
+                    )
+                    .await?;
+                }
+                Ok(())
+            });
+            report
+                .scenario(&scenario.name, method, method.expect_unsupported(), run)
+                .await
+                .with_context(|| format!("scenario {} with {method}", scenario.name))?;
         }
-    });
+    }
+    Ok(())
+}
 
-    // run tests
-    log::info!("setting up fivetran sync");
-    let objects = fivetran::setup_sync(postgres_addr_pub, gel_addr_pub).await?;
-    // tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+/// Validates the synced data, compares it and its column types with Gel and,
+/// if asked to, re-syncs some tables or the whole connection and validates
+/// them again. Then checks the sync of the scenario's mutations, with how
+/// their deletes arrived, and of its incremental change in Gel, if it
+/// defines them.
+async fn validate(
+    client: &fivetran::Client,
+    objects: &fivetran::CreatedObjects,
+    report: &Report,
+    servers: &Servers,
+    scenario: &Scenario,
+    args: &ScenarioArgs,
+) -> anyhow::Result<()> {
+    let postgres_addr = servers.postgres.addr();
+    let checks = &scenario.config.checks;
 
-    // wait a long time, for manual debugging
-    // tokio::select! {
-    //     _ = tokio::signal::ctrl_c() => {},
-    //     _ = tokio::time::sleep(tokio::time::Duration::from_secs(10000)) => {}
-    // }
+    let limitations = &scenario.config.limitations;
+    if !limitations.is_empty() {
+        report.table(
+            "known limitations",
+            vec!["column".into(), "reason".into()],
+            limitations
+                .iter()
+                .map(|l| vec![l.to_string(), l.reason.clone()])
+                .collect(),
+        );
+    }
 
-    fivetran::cleanup(&objects).await?;
+    let source = servers.source(scenario);
+    let parity = async |title: &str| {
+        if args.skip_parity {
+            return Ok(());
+        }
+        log::info!("comparing replicated tables with gel");
+        postgres::validate_parity(
+            servers.gel.addr(),
+            servers.gel.database(),
+            postgres_addr,
+            source.schema_prefix,
+            source.update_method,
+            limitations,
+            title,
+            report,
+        )
+        .await
+    };
 
-    // validating transferred data
     log::info!("validating synced data");
-    postgres::validate_data(postgres.tcp_address).await?;
-    log::info!("sync tests passed");
+    args.checks
+        .validate(postgres_addr, scenario, report)
+        .await?;
+    parity("parity").await?;
 
-    // stop servers
-    drop(postgres);
-    drop(gel_server);
-    Ok(())
-}
+    if !args.skip_column_types {
+        log::info!("validating column types");
+        report
+            .phase("validate column types", async {
+                let mapping = postgres::TypeMapping::load(&args.type_mapping)?;
+                postgres::validate_column_types(
+                    servers.gel.addr(),
+                    servers.gel.database(),
+                    postgres_addr,
+                    source.schema_prefix,
+                    &mapping,
+                    report,
+                )
+                .await
+            })
+            .await?;
+    }
+
+    let resync_tables = &args.resync_tables;
+    if !resync_tables.is_empty() {
+        let tables = resync(client, objects, report, resync_tables).await?;
+
+        log::info!("validating re-synced tables {tables:?}");
+        postgres::validate_tables(postgres_addr, checks, &tables, args.checks.bless, report)
+            .await?;
+    }
 
-async fn init_bore(local_addr: SocketAddr) -> anyhow::Result<bore_cli::client::Client> {
-    let bore_server_ip = env::var("BORE_SERVER_IP")?;
-    let bore_server_secret = env::var("BORE_SERVER_SECRET")?;
+    if args.resync_connection {
+        resync_connection(client, objects, report).await?;
 
-    bore_cli::client::Client::new(
-        &local_addr.ip().to_string(),
-        local_addr.port(),
-        &bore_server_ip,
-        0,
-        Some(&bore_server_secret),
+        log::info!("validating re-synced data");
+        args.checks
+            .validate(postgres_addr, scenario, report)
+            .await?;
+        parity("parity after re-sync").await?;
+    }
+
+    if let Some(mutation) = &scenario.config.mutation
+        && !args.skip_mutation
+    {
+        log::info!("applying mutations");
+        report
+            .infrastructure("apply mutations", async {
+                servers.gel.query_file(&scenario.mutate_file())
+            })
+            .await?;
+        sync_mutations(client, objects, report).await?;
+
+        log::info!("validating mutations");
+        postgres::validate_data(postgres_addr, &mutation.checks, args.checks.bless, report).await?;
+        parity("parity after mutations").await?;
+        if !args.skip_parity {
+            log::info!("checking deletes");
+            postgres::validate_deletes(
+                servers.gel.addr(),
+                servers.gel.database(),
+                postgres_addr,
+                source.schema_prefix,
+                source.update_method,
+                limitations,
+                report,
+            )
+            .await?;
+        }
+    }
+
+    let Some(incremental) = &scenario.config.incremental else {
+        return Ok(());
+    };
+    if args.skip_incremental {
+        return Ok(());
+    }
+    log::info!("checking incremental sync");
+    let validate_change = async || {
+        postgres::validate_data(
+            postgres_addr,
+            &incremental.checks,
+            args.checks.bless,
+            report,
+        )
+        .await
+    };
+    check_cursor(
+        client,
+        objects,
+        report,
+        || servers.gel.query(&incremental.change),
+        validate_change,
     )
     .await
 }
 
-fn get_bore_pub_addr(client: &bore_cli::client::Client) -> anyhow::Result<SocketAddr> {
-    let bore_server_ip = env::var("BORE_SERVER_IP")?;
-    let ip = std::net::IpAddr::from_str(&bore_server_ip)?;
-    Ok(SocketAddr::new(ip, client.remote_port()))
+/// Syncs `change` and runs `validate_change`. If Fivetran exposes the
+/// connection state, also checks that the xmin cursor advanced and that
+/// rewinding it re-reads the change without duplicating it, with
+/// `validate_change` after that sync too. Otherwise only reports a warning
+/// for the cursor.
+async fn check_cursor(
+    client: &fivetran::Client,
+    objects: &fivetran::CreatedObjects,
+    report: &Report,
+    change: impl FnOnce() -> anyhow::Result<()>,
+    validate_change: impl AsyncFn() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let before = fivetran::record_state(client, objects).await?;
+
+    report
+        .infrastructure("change gel data", async { change() })
+        .await?;
+    report
+        .phase("incremental sync", async {
+            report.object(format!("connection {}", objects.connection_id()));
+            fivetran::sync_and_wait(client, objects).await
+        })
+        .await?;
+    validate_change().await?;
+
+    let Some(before) = before else {
+        report.warn(
+            "connection state",
+            "not available",
+            "xmin cursor was not checked",
+        );
+        return Ok(());
+    };
+    let after = fivetran::record_state(client, objects)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("connection state is not available anymore"))?;
+
+    match (before.xmin_cursor(), after.xmin_cursor()) {
+        (Some(before), Some(after)) => {
+            report
+                .phase("xmin cursor advances", async {
+                    anyhow::ensure!(
+                        after > before,
+                        "xmin cursor did not advance after changing data: {before} -> {after}"
+                    );
+                    Ok(())
+                })
+                .await?
+        }
+        _ => report.warn(
+            "connection state",
+            "no xmin cursor",
+            &format!("state before: {}, after: {}", before.0, after.0),
+        ),
+    }
+
+    report
+        .phase("rewind xmin cursor", async {
+            report.object(format!("connection {}", objects.connection_id()));
+            fivetran::rewind_state(client, objects, &before).await
+        })
+        .await?;
+    fivetran::record_state(client, objects).await?;
+    validate_change().await
 }
 
-async fn run_bores(
-    pg_bore: bore_cli::client::Client,
-    gel_bore: bore_cli::client::Client,
-) -> Result<(), Box<dyn std::error::Error>> {
-    tokio::try_join!(pg_bore.listen(), gel_bore.listen())?;
+/// Sets up and syncs a connection for each schema change handling of
+/// `evolution`, each into its own destination schemas. Then runs `migrate`
+/// and, for each connection, reloads its schema config, syncs again and runs
+/// `validate` with the schemas, tables and columns the reload reported as
+/// new.
+#[allow(clippy::too_many_arguments)]
+async fn check_evolution(
+    client: &fivetran::Client,
+    registry: &fivetran::Registry,
+    report: &Report,
+    pg_addr: &fivetran::Endpoint,
+    source: &fivetran::Source<'_>,
+    evolution: &Evolutions,
+    migrate: impl FnOnce() -> anyhow::Result<()>,
+    validate: impl AsyncFn(
+        fivetran::SchemaChangeHandling,
+        &str,
+        &Evolution,
+        String,
+    ) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut connections = Vec::new();
+    for (handling, expected) in evolution.iter() {
+        let prefix = format!("gel_{handling}");
+        let source = fivetran::Source {
+            schema_prefix: &prefix,
+            schema_change_handling: handling,
+            ..*source
+        };
+        let objects = fivetran::setup_sync(client, registry, report, pg_addr, &source).await?;
+        let before = fivetran::schema_objects(client, &objects).await?;
+        connections.push((handling, expected, prefix, objects, before));
+    }
+
+    report
+        .infrastructure("migrate gel", async { migrate() })
+        .await?;
+
+    for (handling, expected, prefix, objects, before) in &connections {
+        let reloaded = report
+            .phase(format!("reload schema ({handling})"), async {
+                report.object(format!("connection {}", objects.connection_id()));
+                let after = fivetran::reload_schema_objects(client, objects).await?;
+                Ok(after.new_since(before))
+            })
+            .await?;
+        report
+            .phase(format!("sync after migration ({handling})"), async {
+                report.object(format!("connection {}", objects.connection_id()));
+                fivetran::sync_and_wait(client, objects).await
+            })
+            .await?;
+        validate(*handling, prefix, expected, reloaded).await?;
+    }
     Ok(())
 }
 
-async fn start_postgres() -> gel_pg_captive::PostgresProcess {
-    tokio::task::spawn_blocking(|| {
-        gel_pg_captive::PostgresBuilder::new()
-            .auth(gel_auth::AuthType::Trust)
-            .with_automatic_mode(gel_pg_captive::Mode::TcpSsl)
-            // .server_option("log_statement", "all")
-            .with_automatic_bin_path()
-            .unwrap()
-            .build()
-            .unwrap()
-    })
-    .await
-    .unwrap()
+/// Compares what reloading the schema config reported as new with
+/// `expected`, like a check: with `bless`, a difference rewrites the
+/// `.expected` file, and without it, a result that was never recorded is
+/// skipped with a warning.
+async fn validate_reload(
+    handling: fivetran::SchemaChangeHandling,
+    expected: &Snapshot,
+    reloaded: String,
+    bless: bool,
+    report: &Report,
+) -> anyhow::Result<()> {
+    if expected.expected.is_none() && !bless {
+        report.warn(
+            "unverified checks",
+            &expected.name,
+            &format!(
+                "skipped, there is no {}; record it with --bless from a sync with Fivetran",
+                expected.expected_file.display()
+            ),
+        );
+        return Ok(());
+    }
+    report
+        .phase(format!("validate reloaded schema ({handling})"), async {
+            match postgres::assert_eq(reloaded, expected.expected.as_deref().unwrap_or_default()) {
+                Ok(()) => Ok(()),
+                Err(mismatch) if bless => {
+                    expected.bless(&mismatch.found)?;
+                    log::warn!(
+                        "{}: rewrote {}:\n{mismatch}",
+                        expected.name,
+                        expected.expected_file.display()
+                    );
+                    Ok(())
+                }
+                Err(mismatch) => Err(mismatch.into()),
+            }
+        })
+        .await
 }
 
-async fn start_gel_server() -> gel_captive::ServerProcess {
-    let server = tokio::task::spawn_blocking(|| {
-        gel_captive::ServerBuilder::new()
-            .log_file_path(Some(
-                path::PathBuf::from_str("./target/gel-server.log").unwrap(),
-            ))
-            .start()
-    })
-    .await
-    .unwrap();
-
-    // apply schema
-    server.apply_schema(&path::PathBuf::from_str("./dbschema").unwrap());
-
-    // run setup
-    let status = server
-        .cli()
-        .arg("query")
-        .arg("--file")
-        .arg("dbschema/setup.edgeql")
-        .status()
-        .unwrap();
-    assert!(status.success());
-
-    server
+/// Syncs changes made by the scenario's mutations, as a phase of the report.
+async fn sync_mutations(
+    client: &fivetran::Client,
+    objects: &fivetran::CreatedObjects,
+    report: &Report,
+) -> anyhow::Result<()> {
+    report
+        .phase("sync mutations", async {
+            report.object(format!("connection {}", objects.connection_id()));
+            fivetran::sync_and_wait(client, objects).await
+        })
+        .await
+}
+
+/// Re-syncs tables as a phase of the report. Returns their destination
+/// tables.
+async fn resync(
+    client: &fivetran::Client,
+    objects: &fivetran::CreatedObjects,
+    report: &Report,
+    tables: &[fivetran::SourceTable],
+) -> anyhow::Result<Vec<String>> {
+    log::info!("re-syncing {tables:?}");
+    report
+        .phase("re-sync tables", async {
+            report.object(format!("connection {}", objects.connection_id()));
+            fivetran::resync_and_wait(client, objects, tables).await
+        })
+        .await
+}
+
+/// Re-syncs the whole connection as a phase of the report.
+async fn resync_connection(
+    client: &fivetran::Client,
+    objects: &fivetran::CreatedObjects,
+    report: &Report,
+) -> anyhow::Result<()> {
+    log::info!("re-syncing the connection");
+    report
+        .phase("re-sync connection", async {
+            report.object(format!("connection {}", objects.connection_id()));
+            fivetran::resync_connection_and_wait(client, objects).await
+        })
+        .await
+}
+
+/// Credentials for runs that never reach Fivetran.
+fn offline_auth() -> fivetran::Auth {
+    fivetran::Auth::KeySecret {
+        key: "offline".into(),
+        secret: "offline".into(),
+    }
+}
+
+/// Records Fivetran API traffic to the file in `FIVETRAN_RECORD`, if set.
+fn with_recording(client: fivetran::Client) -> fivetran::Client {
+    match env::var("FIVETRAN_RECORD") {
+        Ok(path) => {
+            log::info!("recording fivetran api traffic to {path}");
+            client.with_cassette(fivetran::Cassette::record(path))
+        }
+        Err(_) => client,
+    }
 }
@@ -0,0 +1,497 @@
+//! Comparison of every table that Fivetran replicated with its source table
+//! in Gel, read through the SQL adapter of Gel.
+//!
+//! Destination schemas and tables are named after the source ones, with the
+//! schema prefix of the connection, in lower case and with punctuation
+//! replaced, e.g. `public::nested` becomes `gel_public___nested` and
+//! `Movie.actors` becomes `movie_actors`. Names are matched on their letters
+//! and digits only, ignoring case.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+
+use tokio_postgres::Client;
+
+use super::{Mismatch, connect, connect_as, value};
+use crate::fivetran::UpdateMethod;
+use crate::report::Report;
+use crate::scenario::Limitation;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct Table {
+    pub schema: String,
+    pub name: String,
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.schema, self.name)
+    }
+}
+
+/// A column as `information_schema.columns` describes it.
+#[derive(Debug, Clone)]
+pub(super) struct Column {
+    pub name: String,
+    pub data_type: String,
+    pub numeric_precision: Option<i32>,
+    pub nullable: bool,
+}
+
+/// A destination table with its source table.
+pub(super) struct Replica {
+    pub destination: Table,
+    pub source: Table,
+    /// Each destination column with its source column, without `_fivetran_*`
+    /// columns.
+    pub columns: Vec<(Column, Column)>,
+    /// The destination keeps deleted rows, marked in `_fivetran_deleted`.
+    pub soft_deletes: bool,
+}
+
+/// Compares the rows of each table in the `{schema_prefix}_*` schemas of
+/// the destination with the rows of its source, without `_fivetran_*`
+/// columns, columns with known `limitations` and rows marked as deleted.
+/// With an `update_method` that does not sync deletes, rows that are gone
+/// from the source are left out too, see [validate_deletes]. Each table is a
+/// phase of the report named `{title} schema.table`.
+#[allow(clippy::too_many_arguments)]
+pub async fn validate_parity(
+    gel_addr: SocketAddr,
+    gel_database: &str,
+    postgres_addr: SocketAddr,
+    schema_prefix: &str,
+    update_method: UpdateMethod,
+    limitations: &[Limitation],
+    title: &str,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let gel = connect_as(gel_addr, "edgedb", "edgedb", gel_database).await?;
+    let destination = connect(postgres_addr).await?;
+    let replicas = compared_replicas(
+        &gel,
+        &destination,
+        schema_prefix,
+        limitations,
+        title,
+        report,
+    )
+    .await?;
+
+    let total = replicas.len();
+    let mut failed = 0;
+    for replica in &replicas {
+        let name = format!("{title} {}", replica.destination);
+        let compare = replica.compare(&gel, &destination, update_method.syncs_deletes());
+        if let Err(e) = report.phase(&name, compare).await {
+            log::error!("{name} failed:\n{e:#}");
+            failed += 1;
+        }
+    }
+    anyhow::ensure!(
+        failed == 0,
+        "{failed} of {total} tables differ from their source"
+    );
+    Ok(())
+}
+
+/// Checks how rows that were deleted in Gel arrived in each table in the
+/// `{schema_prefix}_*` schemas of the destination, compared like in
+/// [validate_parity]. Each table is a phase of the report named
+/// `{title} schema.table`.
+///
+/// If `update_method` syncs deletes, they have to be marked in
+/// `_fivetran_deleted`, and rows that are marked have to be gone from the
+/// source. Otherwise the rows stay as they are, which is reported as a known
+/// limitation.
+pub async fn validate_deletes(
+    gel_addr: SocketAddr,
+    gel_database: &str,
+    postgres_addr: SocketAddr,
+    schema_prefix: &str,
+    update_method: UpdateMethod,
+    limitations: &[Limitation],
+    report: &Report,
+) -> anyhow::Result<()> {
+    let title = "deletes";
+    let gel = connect_as(gel_addr, "edgedb", "edgedb", gel_database).await?;
+    let destination = connect(postgres_addr).await?;
+    let replicas = compared_replicas(
+        &gel,
+        &destination,
+        schema_prefix,
+        limitations,
+        title,
+        report,
+    )
+    .await?;
+
+    let total = replicas.len();
+    let mut failed = 0;
+    let mut kept = Vec::new();
+    for replica in &replicas {
+        let name = format!("{title} {}", replica.destination);
+        let check = replica.check_deletes(&gel, &destination, update_method.syncs_deletes());
+        match report.phase(&name, check).await {
+            Ok(0) => {}
+            Ok(rows) => kept.push(format!("{rows} in {}", replica.destination)),
+            Err(e) => {
+                log::error!("{name} failed:\n{e:#}");
+                failed += 1;
+            }
+        }
+    }
+    if !update_method.syncs_deletes() {
+        let rows = if kept.is_empty() {
+            "none".to_string()
+        } else {
+            kept.join(", ")
+        };
+        report.warn(
+            "known limitations",
+            &format!("deletes with {update_method}"),
+            &format!(
+                "rows deleted in gel stay in the destination and are not marked in \
+                 _fivetran_deleted, rows kept: {rows}"
+            ),
+        );
+    }
+    anyhow::ensure!(
+        failed == 0,
+        "{failed} of {total} tables do not have the deletes of their source"
+    );
+    Ok(())
+}
+
+/// Maps the replicated tables as a phase of the report and leaves out
+/// columns with known `limitations`.
+async fn compared_replicas(
+    gel: &Client,
+    destination: &Client,
+    schema_prefix: &str,
+    limitations: &[Limitation],
+    title: &str,
+    report: &Report,
+) -> anyhow::Result<Vec<Replica>> {
+    let mut replicas = report
+        .phase(
+            format!("{title}: map replicated tables"),
+            replicas(gel, destination, schema_prefix),
+        )
+        .await?;
+    for replica in &mut replicas {
+        let source = &replica.source;
+        replica.columns.retain(|(_, column)| {
+            !limitations.iter().any(|l| {
+                l.schema == source.schema && l.table == source.name && l.column == column.name
+            })
+        });
+    }
+    Ok(replicas)
+}
+
+/// Pairs each destination table of the connection with its source table.
+pub(super) async fn replicas(
+    gel: &Client,
+    destination: &Client,
+    schema_prefix: &str,
+) -> anyhow::Result<Vec<Replica>> {
+    let sources = table_columns(gel).await?;
+    let mut by_key: BTreeMap<(String, String), &Table> = BTreeMap::new();
+    for table in sources.keys() {
+        if let Some(other) = by_key.insert((key(&table.schema), key(&table.name)), table) {
+            anyhow::bail!("cannot tell apart source tables {other} and {table}");
+        }
+    }
+    let source_schemas: BTreeSet<_> = by_key.keys().map(|(schema, _)| schema.clone()).collect();
+
+    let prefix = format!("{schema_prefix}_");
+    let mut replicas = Vec::new();
+    let mut errors = Vec::new();
+    for (table, columns) in table_columns(destination).await? {
+        // skips schemas of other connections, e.g. `gel_allow_all_public`
+        let Some(schema) = table.schema.strip_prefix(&prefix) else {
+            continue;
+        };
+        if !source_schemas.contains(&key(schema)) || table.name.starts_with("fivetran_") {
+            continue;
+        }
+        let Some(&source) = by_key.get(&(key(schema), key(&table.name))) else {
+            errors.push(format!("{table} has no source table"));
+            continue;
+        };
+
+        let source_columns = &sources[source];
+        let mut pairs = Vec::new();
+        for column in columns.iter().filter(|c| !c.name.starts_with("_fivetran_")) {
+            match source_columns
+                .iter()
+                .find(|c| key(&c.name) == key(&column.name))
+            {
+                Some(source_column) => pairs.push((column.clone(), source_column.clone())),
+                None => errors.push(format!(
+                    "{table}.{} has no source column in {source}",
+                    column.name
+                )),
+            }
+        }
+        replicas.push(Replica {
+            destination: table,
+            source: source.clone(),
+            columns: pairs,
+            soft_deletes: columns.iter().any(|c| c.name == "_fivetran_deleted"),
+        });
+    }
+    anyhow::ensure!(errors.is_empty(), "{}", errors.join("\n"));
+    anyhow::ensure!(
+        !replicas.is_empty(),
+        "no tables in {prefix}* schemas of the destination"
+    );
+
+    // e.g. tables that the scenario skips
+    let replicated: BTreeSet<_> = replicas.iter().map(|r| &r.source).collect();
+    let replicated_schemas: BTreeSet<_> = replicated.iter().map(|t| &t.schema).collect();
+    for table in sources.keys() {
+        if replicated_schemas.contains(&table.schema) && !replicated.contains(table) {
+            log::info!("{table} is not replicated");
+        }
+    }
+    Ok(replicas)
+}
+
+/// Columns of all tables outside of the system schemas, in order.
+async fn table_columns(client: &Client) -> anyhow::Result<BTreeMap<Table, Vec<Column>>> {
+    let rows = client
+        .query(
+            "SELECT table_schema::text, table_name::text, column_name::text, \
+                    data_type::text, numeric_precision::int4, is_nullable::text = 'YES' \
+             FROM information_schema.columns \
+             WHERE table_schema NOT IN ('pg_catalog', 'information_schema') \
+             ORDER BY table_schema, table_name, ordinal_position",
+            &[],
+        )
+        .await?;
+    let mut tables: BTreeMap<Table, Vec<Column>> = BTreeMap::new();
+    for row in rows {
+        let table = Table {
+            schema: row.get(0),
+            name: row.get(1),
+        };
+        tables.entry(table).or_default().push(Column {
+            name: row.get(2),
+            data_type: row.get(3),
+            numeric_precision: row.get(4),
+            nullable: row.get(5),
+        });
+    }
+    Ok(tables)
+}
+
+/// Destination rows that are not marked as deleted.
+const LIVE: &str = "_fivetran_deleted IS NOT TRUE";
+
+/// Destination rows that are marked as deleted.
+const DELETED: &str = "_fivetran_deleted IS TRUE";
+
+impl Replica {
+    /// Compares all rows, regardless of their order. Without
+    /// `syncs_deletes`, destination rows that are not in the source are
+    /// left out.
+    async fn compare(
+        &self,
+        gel: &Client,
+        destination: &Client,
+        syncs_deletes: bool,
+    ) -> anyhow::Result<()> {
+        let source = self.source_rows(gel).await?;
+        let mut found = self.destination_rows(destination, Some(LIVE)).await?;
+        log::info!(
+            "{}: {} rows in {}, {} in the destination",
+            self.destination,
+            source.len(),
+            self.source,
+            found.len()
+        );
+        if !syncs_deletes {
+            let kept = difference(&found, &source);
+            if !kept.is_empty() {
+                log::info!(
+                    "{}: leaving out {} rows that were deleted in {}",
+                    self.destination,
+                    kept.len(),
+                    self.source
+                );
+                found = difference(&found, &kept);
+            }
+        }
+        if source == found {
+            return Ok(());
+        }
+
+        let mismatch = Mismatch {
+            expected: self.text(&source),
+            found: self.text(&found),
+        };
+        Err(anyhow::Error::new(mismatch).context(format!(
+            "{} rows in {}, {} in the destination",
+            source.len(),
+            self.source,
+            found.len()
+        )))
+    }
+
+    /// Checks that rows which are gone from the source are marked as
+    /// deleted, and that no row of the source is. Without `syncs_deletes`,
+    /// only returns the number of rows that are gone from the source but
+    /// kept in the destination.
+    async fn check_deletes(
+        &self,
+        gel: &Client,
+        destination: &Client,
+        syncs_deletes: bool,
+    ) -> anyhow::Result<usize> {
+        let source = self.source_rows(gel).await?;
+        let live = self.destination_rows(destination, Some(LIVE)).await?;
+        let kept = difference(&live, &source);
+        if !syncs_deletes {
+            return Ok(kept.len());
+        }
+
+        anyhow::ensure!(
+            self.soft_deletes,
+            "{} has no _fivetran_deleted column",
+            self.destination
+        );
+        anyhow::ensure!(
+            kept.is_empty(),
+            "{} rows deleted in {} are not marked as deleted:\n{}",
+            kept.len(),
+            self.source,
+            self.text(&kept)
+        );
+        let deleted = self.destination_rows(destination, Some(DELETED)).await?;
+        let existing = difference(&deleted, &difference(&deleted, &source));
+        anyhow::ensure!(
+            existing.is_empty(),
+            "{} rows marked as deleted still exist in {}:\n{}",
+            existing.len(),
+            self.source,
+            self.text(&existing)
+        );
+        log::info!(
+            "{}: {} rows marked as deleted",
+            self.destination,
+            deleted.len()
+        );
+        Ok(0)
+    }
+
+    async fn source_rows(&self, gel: &Client) -> anyhow::Result<Vec<String>> {
+        let columns = self.columns.iter().map(|(_, s)| &s.name);
+        rows(gel, &self.source, columns, None).await
+    }
+
+    /// Rows of the destination, only those that match `filter` if the table
+    /// has soft deletes.
+    async fn destination_rows(
+        &self,
+        destination: &Client,
+        filter: Option<&str>,
+    ) -> anyhow::Result<Vec<String>> {
+        let columns = self.columns.iter().map(|(d, _)| &d.name);
+        let filter = filter.filter(|_| self.soft_deletes);
+        rows(destination, &self.destination, columns, filter).await
+    }
+
+    /// Rows with a header of the destination column names.
+    fn text(&self, rows: &[String]) -> String {
+        let header: Vec<_> = self.columns.iter().map(|(d, _)| d.name.as_str()).collect();
+        format!("{}\n{}\n", header.join(", "), rows.join("\n"))
+    }
+}
+
+/// All rows of the given columns that match `filter`, rendered as text and
+/// sorted. Columns of types that [value::render] cannot decode are cast to
+/// `text`.
+async fn rows(
+    client: &Client,
+    table: &Table,
+    columns: impl Iterator<Item = &String>,
+    filter: Option<&str>,
+) -> anyhow::Result<Vec<String>> {
+    let mut from = format!("FROM {}.{}", quote(&table.schema), quote(&table.name));
+    if let Some(filter) = filter {
+        from += &format!(" WHERE {filter}");
+    }
+    let columns: Vec<_> = columns.map(|c| quote(c)).collect();
+    let statement = client
+        .prepare(&format!("SELECT {} {from}", columns.join(", ")))
+        .await?;
+    let columns: Vec<_> = columns
+        .iter()
+        .zip(statement.columns())
+        .map(|(name, column)| {
+            if value::decodes(column.type_()) {
+                name.clone()
+            } else {
+                format!("{name}::text")
+            }
+        })
+        .collect();
+    let query = format!("SELECT {} {from}", columns.join(", "));
+
+    let mut lines = Vec::new();
+    for row in value::query(client, &query).await? {
+        let values = (0..row.len())
+            .map(|i| value::render(&row, i))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        lines.push(values.join(", "));
+    }
+    lines.sort();
+    Ok(lines)
+}
+
+/// Sorted rows of `a` without those of `b`, each row of `b` removing at
+/// most one equal row of `a`.
+fn difference(a: &[String], b: &[String]) -> Vec<String> {
+    let mut b = b.iter().peekable();
+    let mut r = Vec::new();
+    for row in a {
+        while b.next_if(|x| *x < row).is_some() {}
+        if b.next_if(|x| *x == row).is_none() {
+            r.push(row.clone());
+        }
+    }
+    r
+}
+
+/// Letters and digits of a name, in lower case.
+fn key(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn difference_counts_equal_rows() {
+        let a = lines(&["a", "b", "b", "b", "d"]);
+        let b = lines(&["b", "c", "d", "d"]);
+        assert_eq!(difference(&a, &b), lines(&["a", "b", "b"]));
+        assert_eq!(difference(&b, &a), lines(&["c", "d"]));
+        assert_eq!(difference(&a, &[]), a);
+        assert!(difference(&[], &a).is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! Validation of the types that Fivetran gives destination columns, against
+//! a declarative mapping of Gel scalar types in `type_mapping.toml`.
+//!
+//! Columns are told apart by the `data_type` of their source column in the
+//! SQL adapter of Gel, which shows several Gel types the same way, e.g.
+//! `bigint` and `decimal` both as `numeric`.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::parity::{Column, replicas};
+use super::{connect, connect_as};
+use crate::report::Report;
+
+/// Contents of `type_mapping.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TypeMapping {
+    /// Destination columns that are `NOT NULL`, like the primary key. All
+    /// other columns are nullable.
+    not_null: Vec<String>,
+    #[serde(rename = "scalar")]
+    scalars: Vec<Scalar>,
+}
+
+/// What one source type becomes in the destination.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scalar {
+    /// Gel types that the SQL adapter shows as `sql`.
+    gel: Vec<String>,
+    /// `data_type` of the source column.
+    sql: String,
+    /// `data_type` of the destination column.
+    destination: String,
+    /// `numeric_precision` of the destination column. Not checked if missing.
+    precision: Option<i32>,
+}
+
+impl TypeMapping {
+    pub fn load(path: &Path) -> anyhow::Result<TypeMapping> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        let mapping: TypeMapping = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("invalid {}: {e}", path.display()))?;
+        for (i, scalar) in mapping.scalars.iter().enumerate() {
+            anyhow::ensure!(
+                !mapping.scalars[..i].iter().any(|s| s.sql == scalar.sql),
+                "{} maps {} more than once",
+                path.display(),
+                scalar.sql
+            );
+        }
+        Ok(mapping)
+    }
+}
+
+/// Columns of the same source type that ended up the same in the
+/// destination.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Group {
+    sql: String,
+    data_type: String,
+    precision: Option<i32>,
+    nullable: bool,
+}
+
+/// Checks the `data_type`, `numeric_precision` and nullability of each
+/// replicated column in the `{schema_prefix}_*` schemas of the destination.
+/// Adds a table with what each source type became to the report and fails
+/// with every column that differs from the mapping.
+pub async fn validate_column_types(
+    gel_addr: SocketAddr,
+    gel_database: &str,
+    postgres_addr: SocketAddr,
+    schema_prefix: &str,
+    mapping: &TypeMapping,
+    report: &Report,
+) -> anyhow::Result<()> {
+    let gel = connect_as(gel_addr, "edgedb", "edgedb", gel_database).await?;
+    let destination = connect(postgres_addr).await?;
+
+    // each column with what is wrong with it
+    let mut groups: BTreeMap<Group, Vec<(String, Option<String>)>> = BTreeMap::new();
+    for replica in replicas(&gel, &destination, schema_prefix).await? {
+        for (column, source) in &replica.columns {
+            let group = Group {
+                sql: source.data_type.clone(),
+                data_type: column.data_type.clone(),
+                precision: column.numeric_precision,
+                nullable: column.nullable,
+            };
+            let name = format!("{}.{}", replica.destination, column.name);
+            let problem = check(mapping, column, source);
+            groups.entry(group).or_default().push((name, problem));
+        }
+    }
+
+    let header = [
+        "source type",
+        "gel types",
+        "destination type",
+        "precision",
+        "nullable",
+        "columns",
+        "example",
+        "status",
+    ];
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (group, columns) in &groups {
+        let scalar = mapping.scalars.iter().find(|s| s.sql == group.sql);
+        let mut problems: Vec<_> = columns.iter().filter_map(|(_, p)| p.as_deref()).collect();
+        problems.sort();
+        problems.dedup();
+        rows.push(vec![
+            group.sql.clone(),
+            scalar.map(|s| s.gel.join(", ")).unwrap_or_default(),
+            group.data_type.clone(),
+            group.precision.map(|p| p.to_string()).unwrap_or_default(),
+            if group.nullable { "yes" } else { "no" }.to_string(),
+            columns.len().to_string(),
+            columns[0].0.clone(),
+            if problems.is_empty() {
+                "ok".to_string()
+            } else {
+                problems.join("; ")
+            },
+        ]);
+        for (name, problem) in columns {
+            if let Some(problem) = problem {
+                errors.push(format!("{name}: {problem}"));
+            }
+        }
+    }
+    report.table(
+        "destination column types",
+        header.map(String::from).to_vec(),
+        rows,
+    );
+
+    anyhow::ensure!(
+        errors.is_empty(),
+        "{} columns differ from the type mapping:\n{}",
+        errors.len(),
+        errors.join("\n")
+    );
+    Ok(())
+}
+
+/// What is wrong with a destination column, if anything.
+fn check(mapping: &TypeMapping, column: &Column, source: &Column) -> Option<String> {
+    let Some(scalar) = mapping.scalars.iter().find(|s| s.sql == source.data_type) else {
+        return Some(format!("no mapping for {}", source.data_type));
+    };
+    let mut problems = Vec::new();
+    if column.data_type != scalar.destination {
+        problems.push(format!("expected type {}", scalar.destination));
+    }
+    if let Some(precision) = scalar.precision
+        && column.numeric_precision != Some(precision)
+    {
+        problems.push(format!("expected precision {precision}"));
+    }
+    let nullable = !mapping.not_null.contains(&column.name);
+    if column.nullable != nullable {
+        problems.push(if nullable {
+            "expected nullable".to_string()
+        } else {
+            "expected not null".to_string()
+        });
+    }
+    (!problems.is_empty()).then(|| problems.join(", "))
+}
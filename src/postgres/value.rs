@@ -0,0 +1,583 @@
+//! Rendering of values in query results as text, by their Postgres type, so
+//! queries of checks need no casts to `text` for common types.
+//!
+//! Values look like the text output of Postgres with `TimeZone = 'UTC'` and
+//! `IntervalStyle = 'postgres'`, whatever the settings of the destination.
+//! Floats get their shortest exact representation, so rendering does not
+//! depend on `extra_float_digits` either. Columns of types without a binary
+//! decoding here have to be cast to `text` by the query, for their Postgres
+//! output.
+
+use std::fmt::Write;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use tokio_postgres::types::{FromSql, Kind, Type};
+use tokio_postgres::{Client, Row};
+
+/// Runs `query`, after checking that [render] can decode all columns of its
+/// result.
+pub async fn query(client: &Client, query: &str) -> anyhow::Result<Vec<Row>> {
+    let statement = client.prepare(query).await?;
+    let undecodable: Vec<_> = statement
+        .columns()
+        .iter()
+        .filter(|c| !decodes(c.type_()))
+        .map(|c| format!("{} ({})", c.name(), c.type_()))
+        .collect();
+    anyhow::ensure!(
+        undecodable.is_empty(),
+        "cannot render columns {} of the result, cast them to text in the query",
+        undecodable.join(", ")
+    );
+    Ok(client.query(&statement, &[]).await?)
+}
+
+/// Whether [render_value] decodes values of `ty`.
+pub(super) fn decodes(ty: &Type) -> bool {
+    match ty.kind() {
+        Kind::Array(member) => decodes(member),
+        Kind::Domain(base) => decodes(base),
+        Kind::Enum(_) => true,
+        _ => matches!(
+            *ty,
+            Type::BOOL
+                | Type::INT2
+                | Type::INT4
+                | Type::INT8
+                | Type::OID
+                | Type::FLOAT4
+                | Type::FLOAT8
+                | Type::NUMERIC
+                | Type::UUID
+                | Type::DATE
+                | Type::TIME
+                | Type::TIMESTAMP
+                | Type::TIMESTAMPTZ
+                | Type::INTERVAL
+                | Type::BYTEA
+                | Type::JSONB
+                | Type::TEXT
+                | Type::VARCHAR
+                | Type::BPCHAR
+                | Type::NAME
+                | Type::JSON
+        ),
+    }
+}
+
+/// Renders column `i` of `row`, with `NULL` for nulls.
+pub fn render(row: &Row, i: usize) -> anyhow::Result<String> {
+    let column = &row.columns()[i];
+    match row.try_get::<_, Option<Raw>>(i)? {
+        None => Ok("NULL".into()),
+        Some(Raw(raw)) => render_value(column.type_(), raw).map_err(|e| {
+            anyhow::anyhow!(
+                "cannot render {} of type {}: {e}",
+                column.name(),
+                column.type_()
+            )
+        }),
+    }
+}
+
+/// A value in the binary format of its type.
+struct Raw<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for Raw<'a> {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+        Ok(Raw(raw))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+fn render_value(ty: &Type, raw: &[u8]) -> anyhow::Result<String> {
+    match ty.kind() {
+        Kind::Array(member) => return render_array(member, raw),
+        Kind::Domain(base) => return render_value(base, raw),
+        _ => {}
+    }
+    let mut buf = raw;
+    let buf = &mut buf;
+    Ok(match *ty {
+        Type::BOOL => (take::<1>(buf)?[0] != 0).to_string(),
+        Type::INT2 => i16::from_be_bytes(take(buf)?).to_string(),
+        Type::INT4 => i32::from_be_bytes(take(buf)?).to_string(),
+        Type::INT8 => i64::from_be_bytes(take(buf)?).to_string(),
+        Type::OID => u32::from_be_bytes(take(buf)?).to_string(),
+        Type::FLOAT4 => {
+            let value = f32::from_be_bytes(take(buf)?);
+            match special_float(value.into()) {
+                Some(special) => special.into(),
+                None => float(value.to_string(), format!("{value:e}"), 6),
+            }
+        }
+        Type::FLOAT8 => {
+            let value = f64::from_be_bytes(take(buf)?);
+            match special_float(value) {
+                Some(special) => special.into(),
+                None => float(value.to_string(), format!("{value:e}"), 15),
+            }
+        }
+        Type::NUMERIC => numeric(buf)?,
+        Type::UUID => {
+            let hex = hex(&take::<16>(buf)?);
+            format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            )
+        }
+        Type::DATE => match i32::from_be_bytes(take(buf)?) {
+            i32::MAX => "infinity".into(),
+            i32::MIN => "-infinity".into(),
+            days => epoch()
+                .date()
+                .checked_add_signed(TimeDelta::days(days.into()))
+                .ok_or_else(|| anyhow::anyhow!("date out of range"))?
+                .format("%Y-%m-%d")
+                .to_string(),
+        },
+        Type::TIME => match i64::from_be_bytes(take(buf)?) {
+            // NaiveTime has no end of the day
+            86_400_000_000 => "24:00:00".into(),
+            micros => {
+                let time = NaiveTime::MIN + TimeDelta::microseconds(micros);
+                format!("{}{}", time.format("%H:%M:%S"), fraction(micros))
+            }
+        },
+        Type::TIMESTAMP => timestamp(i64::from_be_bytes(take(buf)?))?,
+        Type::TIMESTAMPTZ => match i64::from_be_bytes(take(buf)?) {
+            micros @ (i64::MAX | i64::MIN) => timestamp(micros)?,
+            micros => format!("{}+00", timestamp(micros)?),
+        },
+        Type::INTERVAL => {
+            let micros = i64::from_be_bytes(take(buf)?);
+            let days = i32::from_be_bytes(take(buf)?);
+            let months = i32::from_be_bytes(take(buf)?);
+            interval(months, days, micros)
+        }
+        Type::BYTEA => format!("\\x{}", hex(raw)),
+        Type::JSONB => {
+            anyhow::ensure!(take::<1>(buf)? == [1], "unknown jsonb version");
+            String::from_utf8(buf.to_vec())?
+        }
+        // sent as text
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::JSON => {
+            String::from_utf8(raw.to_vec())?
+        }
+        _ if matches!(ty.kind(), Kind::Enum(_)) => String::from_utf8(raw.to_vec())?,
+        // [query] rejects these, they have to be cast to text
+        _ => anyhow::bail!("no binary decoding"),
+    })
+}
+
+/// Renders an array like Postgres, e.g. `{1,NULL,3}` or `{{a,b},{c,d}}`.
+fn render_array(member: &Type, raw: &[u8]) -> anyhow::Result<String> {
+    let mut buf = raw;
+    let buf = &mut buf;
+    let dimensions = i32::from_be_bytes(take(buf)?);
+    let _has_nulls = i32::from_be_bytes(take(buf)?);
+    let _member_oid = u32::from_be_bytes(take(buf)?);
+    let mut lengths = Vec::new();
+    for _ in 0..dimensions {
+        lengths.push(usize::try_from(i32::from_be_bytes(take(buf)?))?);
+        let _lower_bound = i32::from_be_bytes(take(buf)?);
+    }
+    if lengths.is_empty() {
+        return Ok("{}".into());
+    }
+
+    let mut elements = Vec::new();
+    for _ in 0..lengths.iter().product::<usize>() {
+        let element = match i32::from_be_bytes(take(buf)?) {
+            -1 => "NULL".to_string(),
+            len => {
+                let len = usize::try_from(len)?;
+                anyhow::ensure!(buf.len() >= len, "array element is cut off");
+                let (element, rest) = buf.split_at(len);
+                *buf = rest;
+                quote_element(render_value(member, element)?)
+            }
+        };
+        elements.push(element);
+    }
+
+    fn nest(lengths: &[usize], elements: &mut impl Iterator<Item = String>) -> String {
+        let mut r = String::from("{");
+        for i in 0..lengths[0] {
+            if i > 0 {
+                r += ",";
+            }
+            if lengths.len() == 1 {
+                r += &elements.next().unwrap();
+            } else {
+                r += &nest(&lengths[1..], elements);
+            }
+        }
+        r += "}";
+        r
+    }
+    Ok(nest(&lengths, &mut elements.into_iter()))
+}
+
+/// Quotes an array element where Postgres would.
+fn quote_element(element: String) -> String {
+    let needs_quotes = element.is_empty()
+        || element.eq_ignore_ascii_case("NULL")
+        || element
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_ascii_whitespace());
+    if !needs_quotes {
+        return element;
+    }
+    let mut r = String::from("\"");
+    for c in element.chars() {
+        if matches!(c, '"' | '\\') {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r.push('"');
+    r
+}
+
+/// Decodes a numeric: a header and digits in base 10000.
+fn numeric(buf: &mut &[u8]) -> anyhow::Result<String> {
+    let ndigits = i16::from_be_bytes(take(buf)?);
+    let weight = i16::from_be_bytes(take(buf)?);
+    let sign = u16::from_be_bytes(take(buf)?);
+    let scale = u16::from_be_bytes(take(buf)?);
+    let digits = (0..ndigits)
+        .map(|_| Ok(i16::from_be_bytes(take(buf)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut r = match sign {
+        0x0000 => String::new(),
+        0x4000 => "-".to_string(),
+        0xC000 => return Ok("NaN".into()),
+        0xD000 => return Ok("Infinity".into()),
+        0xF000 => return Ok("-Infinity".into()),
+        _ => anyhow::bail!("invalid numeric sign {sign:#x}"),
+    };
+    // digit `i` is multiplied with 10000^(weight - i)
+    let digit = |i: i32| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i))
+            .copied()
+            .unwrap_or(0)
+    };
+    let weight = i32::from(weight);
+    if weight < 0 {
+        r += "0";
+    }
+    for i in 0..=weight {
+        if i == 0 {
+            let _ = write!(r, "{}", digit(i));
+        } else {
+            let _ = write!(r, "{:04}", digit(i));
+        }
+    }
+    if scale > 0 {
+        let mut fraction = String::new();
+        let mut i = weight + 1;
+        while fraction.len() < usize::from(scale) {
+            let _ = write!(fraction, "{:04}", digit(i));
+            i += 1;
+        }
+        fraction.truncate(scale.into());
+        r += ".";
+        r += &fraction;
+    }
+    Ok(r)
+}
+
+fn special_float(value: f64) -> Option<&'static str> {
+    if value.is_nan() {
+        Some("NaN")
+    } else if value == f64::INFINITY {
+        Some("Infinity")
+    } else if value == f64::NEG_INFINITY {
+        Some("-Infinity")
+    } else {
+        None
+    }
+}
+
+/// Picks between the plain and scientific notation of the shortest exact
+/// representation of a float, where Postgres does.
+fn float(plain: String, scientific: String, precision: i32) -> String {
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if (-4..precision).contains(&exponent) {
+        return plain;
+    }
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.abs())
+}
+
+/// Midnight of 2000-01-01, from which Postgres counts dates and timestamps.
+fn epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_time(NaiveTime::MIN)
+}
+
+fn timestamp(micros: i64) -> anyhow::Result<String> {
+    Ok(match micros {
+        i64::MAX => "infinity".into(),
+        i64::MIN => "-infinity".into(),
+        micros => {
+            let timestamp = epoch()
+                .checked_add_signed(TimeDelta::microseconds(micros))
+                .ok_or_else(|| anyhow::anyhow!("timestamp out of range"))?;
+            format!(
+                "{}{}",
+                timestamp.format("%Y-%m-%d %H:%M:%S"),
+                fraction(micros)
+            )
+        }
+    })
+}
+
+fn interval(months: i32, days: i32, micros: i64) -> String {
+    fn unit(parts: &mut Vec<String>, value: i32, name: &str) {
+        match value {
+            0 => {}
+            1 => parts.push(format!("{value} {name}")),
+            _ => parts.push(format!("{value} {name}s")),
+        }
+    }
+    let mut parts = Vec::new();
+    unit(&mut parts, months / 12, "year");
+    unit(&mut parts, months % 12, "mon");
+    unit(&mut parts, days, "day");
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 { "-" } else { "" };
+        let abs = micros.unsigned_abs();
+        let secs = abs / 1_000_000;
+        parts.push(format!(
+            "{sign}{:02}:{:02}:{:02}{}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            fraction((abs % 1_000_000) as i64)
+        ));
+    }
+    parts.join(" ")
+}
+
+/// Fractional seconds of a count of microseconds, without trailing zeros.
+fn fraction(micros: i64) -> String {
+    let micros = micros.rem_euclid(1_000_000);
+    if micros == 0 {
+        return String::new();
+    }
+    format!(".{micros:06}").trim_end_matches('0').to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut r = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(r, "{b:02x}");
+    }
+    r
+}
+
+/// Splits the first `N` bytes off `buf`.
+fn take<const N: usize>(buf: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    anyhow::ensure!(buf.len() >= N, "value is cut off");
+    let (head, rest) = buf.split_at(N);
+    *buf = rest;
+    Ok(head.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(ty: Type, raw: &[u8]) -> String {
+        render_value(&ty, raw).unwrap()
+    }
+
+    /// Binary numeric: ndigits, weight, sign, dscale and base 10000 digits.
+    fn numeric(weight: i16, sign: u16, scale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.extend((digits.len() as i16).to_be_bytes());
+        raw.extend(weight.to_be_bytes());
+        raw.extend(sign.to_be_bytes());
+        raw.extend(scale.to_be_bytes());
+        for digit in digits {
+            raw.extend(digit.to_be_bytes());
+        }
+        raw
+    }
+
+    /// Binary array of `int4` elements, `None` for NULL.
+    fn int4_array(lengths: &[i32], elements: &[Option<i32>]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.extend((lengths.len() as i32).to_be_bytes());
+        raw.extend(i32::from(elements.contains(&None)).to_be_bytes());
+        raw.extend(Type::INT4.oid().to_be_bytes());
+        for length in lengths {
+            raw.extend(length.to_be_bytes());
+            raw.extend(1i32.to_be_bytes());
+        }
+        for element in elements {
+            match element {
+                Some(value) => {
+                    raw.extend(4i32.to_be_bytes());
+                    raw.extend(value.to_be_bytes());
+                }
+                None => raw.extend((-1i32).to_be_bytes()),
+            }
+        }
+        raw
+    }
+
+    #[test]
+    fn numerics() {
+        assert_eq!(
+            render(Type::NUMERIC, &numeric(1, 0, 2, &[12, 3400])),
+            "123400.00"
+        );
+        assert_eq!(
+            render(Type::NUMERIC, &numeric(0, 0x4000, 3, &[1, 5000])),
+            "-1.500"
+        );
+        // 0.0012 and 0.00000001
+        assert_eq!(render(Type::NUMERIC, &numeric(-1, 0, 4, &[12])), "0.0012");
+        assert_eq!(
+            render(Type::NUMERIC, &numeric(-2, 0, 8, &[1])),
+            "0.00000001"
+        );
+        assert_eq!(render(Type::NUMERIC, &numeric(0, 0, 0, &[])), "0");
+        assert_eq!(render(Type::NUMERIC, &numeric(0, 0xC000, 0, &[])), "NaN");
+        assert_eq!(
+            render(Type::NUMERIC, &numeric(0, 0xF000, 0, &[])),
+            "-Infinity"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(render(Type::FLOAT8, &(-0.0f64).to_be_bytes()), "-0");
+        assert_eq!(render(Type::FLOAT4, &(-0.0f32).to_be_bytes()), "-0");
+        assert_eq!(render(Type::FLOAT8, &f64::NAN.to_be_bytes()), "NaN");
+        assert_eq!(render(Type::FLOAT4, &f32::NAN.to_be_bytes()), "NaN");
+        assert_eq!(
+            render(Type::FLOAT8, &f64::INFINITY.to_be_bytes()),
+            "Infinity"
+        );
+        assert_eq!(
+            render(Type::FLOAT4, &f32::NEG_INFINITY.to_be_bytes()),
+            "-Infinity"
+        );
+        assert_eq!(render(Type::FLOAT8, &0.1f64.to_be_bytes()), "0.1");
+        assert_eq!(render(Type::FLOAT4, &0.1f32.to_be_bytes()), "0.1");
+        assert_eq!(render(Type::FLOAT8, &1e20f64.to_be_bytes()), "1e+20");
+        assert_eq!(render(Type::FLOAT8, &1.5e-5f64.to_be_bytes()), "1.5e-05");
+    }
+
+    #[test]
+    fn timestamps() {
+        // 2024-02-29 12:34:56.789 is this many microseconds after 2000-01-01
+        let micros: i64 = 762_525_296_789_000;
+        assert_eq!(
+            render(Type::TIMESTAMP, &micros.to_be_bytes()),
+            "2024-02-29 12:34:56.789"
+        );
+        assert_eq!(
+            render(Type::TIMESTAMPTZ, &micros.to_be_bytes()),
+            "2024-02-29 12:34:56.789+00"
+        );
+        assert_eq!(
+            render(Type::TIMESTAMPTZ, &i64::MAX.to_be_bytes()),
+            "infinity"
+        );
+        assert_eq!(
+            render(Type::TIMESTAMPTZ, &i64::MIN.to_be_bytes()),
+            "-infinity"
+        );
+        assert_eq!(render(Type::TIMESTAMP, &i64::MAX.to_be_bytes()), "infinity");
+        assert_eq!(
+            render(Type::TIMESTAMP, &i64::MIN.to_be_bytes()),
+            "-infinity"
+        );
+        assert_eq!(render(Type::DATE, &i32::MIN.to_be_bytes()), "-infinity");
+    }
+
+    #[test]
+    fn times() {
+        let micros: i64 = (13 * 3600 + 5 * 60 + 7) * 1_000_000 + 250_000;
+        assert_eq!(render(Type::TIME, &micros.to_be_bytes()), "13:05:07.25");
+        assert_eq!(render(Type::TIME, &0i64.to_be_bytes()), "00:00:00");
+        assert_eq!(
+            render(Type::TIME, &86_400_000_000i64.to_be_bytes()),
+            "24:00:00"
+        );
+    }
+
+    #[test]
+    fn intervals() {
+        let interval = |micros: i64, days: i32, months: i32| {
+            let mut raw = micros.to_be_bytes().to_vec();
+            raw.extend(days.to_be_bytes());
+            raw.extend(months.to_be_bytes());
+            render(Type::INTERVAL, &raw)
+        };
+        let micros = (4 * 3600 + 5 * 60 + 6) * 1_000_000 + 500_000;
+        assert_eq!(interval(micros, 3, 14), "1 year 2 mons 3 days 04:05:06.5");
+        assert_eq!(interval(-1_000_000, -1, -1), "-1 mons -1 days -00:00:01");
+        assert_eq!(interval(0, 1, 12), "1 year 1 day");
+        assert_eq!(interval(0, 0, 0), "00:00:00");
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(
+            render(
+                Type::INT4_ARRAY,
+                &int4_array(&[2, 2], &[Some(1), None, Some(3), Some(4)])
+            ),
+            "{{1,NULL},{3,4}}"
+        );
+        assert_eq!(
+            render(Type::INT4_ARRAY, &int4_array(&[3], &[None, None, Some(-2)])),
+            "{NULL,NULL,-2}"
+        );
+        assert_eq!(render(Type::INT4_ARRAY, &int4_array(&[], &[])), "{}");
+
+        // text elements are quoted where Postgres does
+        let mut raw = Vec::new();
+        raw.extend(1i32.to_be_bytes());
+        raw.extend(1i32.to_be_bytes());
+        raw.extend(Type::TEXT.oid().to_be_bytes());
+        raw.extend(4i32.to_be_bytes());
+        raw.extend(1i32.to_be_bytes());
+        for element in [Some("a b"), None, Some("NULL"), Some("x")] {
+            match element {
+                Some(text) => {
+                    raw.extend((text.len() as i32).to_be_bytes());
+                    raw.extend(text.as_bytes());
+                }
+                None => raw.extend((-1i32).to_be_bytes()),
+            }
+        }
+        assert_eq!(render(Type::TEXT_ARRAY, &raw), r#"{"a b",NULL,"NULL",x}"#);
+    }
+
+    #[test]
+    fn types_without_binary_decoding() {
+        assert!(decodes(&Type::TEXT_ARRAY));
+        assert!(!decodes(&Type::INT4_RANGE));
+        assert!(!decodes(&Type::INET_ARRAY));
+        assert!(render_value(&Type::INT4_RANGE, &[1]).is_err());
+    }
+}
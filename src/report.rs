@@ -0,0 +1,599 @@
+//! What happened during a run: timed phases and warnings, for logs and for
+//! machine-readable reports in JSON and JUnit XML.
+
+use std::fmt::{Display, Write as _};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::postgres::Mismatch;
+
+/// Where to write the report of a run.
+#[derive(clap::Args)]
+pub struct ReportArgs {
+    /// Write phases of the run with their durations, outcomes, Fivetran
+    /// objects and diffs, as JSON
+    #[arg(long, value_name = "PATH")]
+    pub report_json: Option<PathBuf>,
+
+    /// Write the same as JUnit XML, one test case per phase
+    #[arg(long, value_name = "PATH")]
+    pub report_junit: Option<PathBuf>,
+}
+
+/// Phases of a run and findings that are worth reporting, but do not fail
+/// it. Shared by reference, so phases can run concurrently.
+pub struct Report {
+    started_at: DateTime<Utc>,
+    started: Instant,
+    phases: Mutex<Vec<Phase>>,
+    warnings: Mutex<Vec<Warning>>,
+    /// Scenario and update method that new phases belong to.
+    scenario: Mutex<Option<String>>,
+    scenarios: Mutex<Vec<ScenarioRun>>,
+    tables: Mutex<Vec<Table>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Phase {
+    /// Scenario and update method, as `scenario/method`. `None` for phases
+    /// that are not part of a scenario, e.g. cleaning up objects of earlier
+    /// runs.
+    pub scenario: Option<String>,
+    pub name: String,
+    pub outcome: Outcome,
+    /// `None` while the phase runs, and for phases that never finished
+    /// because the run was interrupted.
+    pub duration_secs: Option<f64>,
+    /// Fivetran objects the phase created or worked on, e.g.
+    /// `connection abc_xyz`.
+    pub objects: Vec<String>,
+    pub error: Option<String>,
+    /// For validation queries that returned something else than expected.
+    pub diff: Option<Diff>,
+    /// Sets up or tears down what the scenario runs on, so a failure is
+    /// not up to the update method.
+    #[serde(skip)]
+    pub infrastructure: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Unfinished,
+    Passed,
+    Failed,
+    /// Failed with an update method that Gel does not support yet.
+    Unsupported,
+}
+
+/// Outcome of a scenario with one update method.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioRun {
+    pub scenario: String,
+    pub update_method: String,
+    pub outcome: Outcome,
+    /// Gel does not support the update method yet, so the scenario is
+    /// expected to fail.
+    pub expect_unsupported: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diff {
+    pub expected: String,
+    pub found: String,
+}
+
+/// Findings that read best as a table, e.g. how Gel types arrive in the
+/// destination.
+#[derive(Debug, Clone, Serialize)]
+pub struct Table {
+    /// Scenario and update method, like in [Phase].
+    pub scenario: Option<String>,
+    pub title: String,
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    /// What produced the warning, e.g. `destination setup tests`.
+    pub source: String,
+    pub title: String,
+    pub message: String,
+}
+
+/// Everything in a written report.
+#[derive(Serialize)]
+struct Summary {
+    started_at: DateTime<Utc>,
+    duration_secs: f64,
+    passed: bool,
+    /// Error the run failed with, which might not belong to any phase.
+    error: Option<String>,
+    phases: Vec<Phase>,
+    warnings: Vec<Warning>,
+    scenarios: Vec<ScenarioRun>,
+    tables: Vec<Table>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Report {
+            started_at: Utc::now(),
+            started: Instant::now(),
+            phases: Mutex::new(Vec::new()),
+            warnings: Mutex::new(Vec::new()),
+            scenario: Mutex::new(None),
+            scenarios: Mutex::new(Vec::new()),
+            tables: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs `run`, with all phases it starts belonging to `scenario` with
+    /// `update_method`, and records how it ended.
+    ///
+    /// With `expect_unsupported`, a failure in phases that sync or validate
+    /// is recorded as [Outcome::Unsupported] and does not fail the run, and
+    /// a success is worth a warning. Failures in infrastructure phases, like
+    /// starting servers or cleaning up, and outside of any phase still fail
+    /// it.
+    pub async fn scenario(
+        &self,
+        scenario: &str,
+        update_method: impl Display,
+        expect_unsupported: bool,
+        run: impl Future<Output = anyhow::Result<()>>,
+    ) -> anyhow::Result<()> {
+        let update_method = update_method.to_string();
+        let scope = format!("{scenario}/{update_method}");
+        *self.scenario.lock().unwrap() = Some(scope.clone());
+        let res = run.await;
+        *self.scenario.lock().unwrap() = None;
+
+        let outcome = match (&res, expect_unsupported) {
+            (Ok(()), false) => Outcome::Passed,
+            (Ok(()), true) => {
+                self.warn(
+                    &format!("update method {update_method}"),
+                    "passed unexpectedly",
+                    &format!("scenario {scenario} passed, Gel might support it now"),
+                );
+                Outcome::Passed
+            }
+            (Err(_), false) => Outcome::Failed,
+            (Err(e), true) => {
+                let mut phases = self.phases.lock().unwrap();
+                // whether each failed phase is an infrastructure phase
+                let failed: Vec<bool> = phases
+                    .iter()
+                    .filter(|p| p.scenario.as_ref() == Some(&scope) && p.outcome == Outcome::Failed)
+                    .map(|p| p.infrastructure)
+                    .collect();
+                if failed.is_empty() || failed.contains(&true) {
+                    log::info!("{scope} failed, not because of {update_method}: {e:#}");
+                    Outcome::Failed
+                } else {
+                    log::info!("{scope} failed as expected: {e:#}");
+                    for phase in phases.iter_mut() {
+                        if phase.scenario.as_ref() == Some(&scope)
+                            && phase.outcome == Outcome::Failed
+                        {
+                            phase.outcome = Outcome::Unsupported;
+                        }
+                    }
+                    Outcome::Unsupported
+                }
+            }
+        };
+        self.scenarios.lock().unwrap().push(ScenarioRun {
+            scenario: scenario.to_string(),
+            update_method,
+            outcome,
+            expect_unsupported,
+            error: res.as_ref().err().map(|e| format!("{e:#}")),
+        });
+        match outcome {
+            Outcome::Unsupported => Ok(()),
+            _ => res,
+        }
+    }
+
+    /// Runs `phase` and records how long it took and how it ended. Phases
+    /// with the same name in the same scenario are numbered.
+    pub async fn phase<T>(
+        &self,
+        name: impl Into<String>,
+        phase: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        self.run_phase(name.into(), false, phase).await
+    }
+
+    /// Like [Report::phase], for setting up or tearing down what a scenario
+    /// runs on. Failing it fails the scenario with any update method.
+    pub async fn infrastructure<T>(
+        &self,
+        name: impl Into<String>,
+        phase: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        self.run_phase(name.into(), true, phase).await
+    }
+
+    async fn run_phase<T>(
+        &self,
+        name: String,
+        infrastructure: bool,
+        phase: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let index = {
+            let mut phases = self.phases.lock().unwrap();
+            let scenario = self.scenario.lock().unwrap().clone();
+            let mut name = name;
+            let same = phases
+                .iter()
+                .filter(|p| p.scenario == scenario)
+                .filter(|p| p.name == name || p.name.starts_with(&format!("{name} (")))
+                .count();
+            if same > 0 {
+                name = format!("{name} ({})", same + 1);
+            }
+            log::debug!("phase {name}");
+            phases.push(Phase {
+                scenario,
+                name,
+                outcome: Outcome::Unfinished,
+                duration_secs: None,
+                objects: Vec::new(),
+                error: None,
+                diff: None,
+                infrastructure,
+            });
+            phases.len() - 1
+        };
+
+        let started = Instant::now();
+        let res = phase.await;
+
+        let mut phases = self.phases.lock().unwrap();
+        let phase = &mut phases[index];
+        phase.duration_secs = Some(started.elapsed().as_secs_f64());
+        match &res {
+            Ok(_) => phase.outcome = Outcome::Passed,
+            Err(e) => {
+                phase.outcome = Outcome::Failed;
+                phase.error = Some(format!("{e:#}"));
+                phase.diff = e.downcast_ref::<Mismatch>().map(|m| Diff {
+                    expected: m.expected.clone(),
+                    found: m.found.clone(),
+                });
+            }
+        }
+        log::debug!(
+            "phase {}: {:?} after {:.1}s",
+            phase.name,
+            phase.outcome,
+            started.elapsed().as_secs_f64()
+        );
+        res
+    }
+
+    /// Attaches a Fivetran object to the innermost running phase.
+    pub fn object(&self, object: impl Display) {
+        let mut phases = self.phases.lock().unwrap();
+        if let Some(phase) = phases
+            .iter_mut()
+            .rev()
+            .find(|p| p.outcome == Outcome::Unfinished)
+        {
+            phase.objects.push(object.to_string());
+        }
+    }
+
+    /// Logs a table and adds it to the report.
+    pub fn table(&self, title: &str, header: Vec<String>, rows: Vec<Vec<String>>) {
+        log::info!("{title}:");
+        let lines: Vec<Vec<&str>> = std::iter::once(&header)
+            .chain(&rows)
+            .map(|row| row.iter().map(String::as_str).collect())
+            .collect();
+        log_table(&lines);
+        self.tables.lock().unwrap().push(Table {
+            scenario: self.scenario.lock().unwrap().clone(),
+            title: title.to_string(),
+            header,
+            rows,
+        });
+    }
+
+    pub fn warn(&self, source: &str, title: &str, message: &str) {
+        log::warn!("{source}: {title}: {message}");
+        self.warnings.lock().unwrap().push(Warning {
+            source: source.to_string(),
+            title: title.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn log_summary(&self) {
+        self.log_scenarios();
+
+        let warnings = self.warnings.lock().unwrap();
+        if warnings.is_empty() {
+            return;
+        }
+        log::warn!("{} warning(s):", warnings.len());
+        for w in warnings.iter() {
+            log::warn!("  {}: {}: {}", w.source, w.title, w.message);
+        }
+    }
+
+    /// Logs outcomes of scenarios as a table, with a column per update
+    /// method.
+    fn log_scenarios(&self) {
+        let runs = self.scenarios.lock().unwrap();
+        if runs.is_empty() {
+            return;
+        }
+        let mut scenarios: Vec<&str> = Vec::new();
+        let mut methods: Vec<&str> = Vec::new();
+        for run in runs.iter() {
+            if !scenarios.contains(&run.scenario.as_str()) {
+                scenarios.push(&run.scenario);
+            }
+            if !methods.contains(&run.update_method.as_str()) {
+                methods.push(&run.update_method);
+            }
+        }
+
+        let cell = |scenario: &str, method: &str| {
+            let run = runs
+                .iter()
+                .find(|r| r.scenario == scenario && r.update_method == method);
+            match run {
+                None => "-",
+                Some(r) => match r.outcome {
+                    Outcome::Unfinished => "unfinished",
+                    Outcome::Passed if r.expect_unsupported => "passed (unexpectedly)",
+                    Outcome::Passed => "passed",
+                    Outcome::Failed => "FAILED",
+                    Outcome::Unsupported => "unsupported (expected)",
+                },
+            }
+        };
+        let mut table = vec![
+            std::iter::once("scenario")
+                .chain(methods.iter().copied())
+                .collect::<Vec<_>>(),
+        ];
+        for &scenario in &scenarios {
+            let mut row = vec![scenario];
+            row.extend(methods.iter().map(|&m| cell(scenario, m)));
+            table.push(row);
+        }
+
+        log::info!("scenarios by update method:");
+        log_table(&table);
+    }
+
+    fn summary<T>(&self, res: &anyhow::Result<T>) -> Summary {
+        Summary {
+            started_at: self.started_at,
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            passed: res.is_ok(),
+            error: res.as_ref().err().map(|e| format!("{e:#}")),
+            phases: self.phases.lock().unwrap().clone(),
+            warnings: self.warnings.lock().unwrap().clone(),
+            scenarios: self.scenarios.lock().unwrap().clone(),
+            tables: self.tables.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Logs rows with aligned columns.
+pub(crate) fn log_table(rows: &[Vec<&str>]) {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, value) in row.iter().enumerate() {
+            let width = value.chars().count();
+            match widths.get_mut(i) {
+                Some(w) => *w = (*w).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    for row in rows {
+        let mut line = String::new();
+        for (value, width) in row.iter().zip(&widths) {
+            let _ = write!(line, "  {value:width$}");
+        }
+        log::info!("{}", line.trim_end());
+    }
+}
+
+impl ReportArgs {
+    /// Writes the report of a run that ended with `res`.
+    pub fn write<T>(&self, report: &Report, res: &anyhow::Result<T>) -> anyhow::Result<()> {
+        if self.report_json.is_none() && self.report_junit.is_none() {
+            return Ok(());
+        }
+        let summary = report.summary(res);
+        if let Some(path) = &self.report_json {
+            log::info!("writing report to {}", path.display());
+            std::fs::write(path, serde_json::to_string_pretty(&summary)?)?;
+        }
+        if let Some(path) = &self.report_junit {
+            log::info!("writing junit report to {}", path.display());
+            std::fs::write(path, junit(&summary))?;
+        }
+        Ok(())
+    }
+}
+
+/// One test suite with a test case per phase, classed by the phase's
+/// scenario. Phases that failed with an update method that Gel does not
+/// support are skipped. An error of the run that no phase failed with, e.g.
+/// an interruption, becomes a failed `run` case.
+fn junit(summary: &Summary) -> String {
+    let mut cases = String::new();
+    let mut failures = 0;
+    let mut skipped = 0;
+    for phase in &summary.phases {
+        let _ = write!(
+            cases,
+            "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+            escape(phase.scenario.as_deref().unwrap_or("runner")),
+            escape(&phase.name),
+            phase.duration_secs.unwrap_or_default()
+        );
+        let failure = match phase.outcome {
+            Outcome::Passed | Outcome::Unsupported => None,
+            Outcome::Failed => phase.error.as_deref(),
+            Outcome::Unfinished => Some("did not finish"),
+        };
+        match failure {
+            Some(error) => {
+                failures += 1;
+                let message = error.lines().next().unwrap_or_default();
+                let _ = writeln!(
+                    cases,
+                    ">\n      <failure message=\"{}\">{}</failure>",
+                    escape(message),
+                    escape(error)
+                );
+            }
+            None => cases.push_str(">\n"),
+        }
+        if phase.outcome == Outcome::Unsupported {
+            skipped += 1;
+            let error = phase.error.as_deref().unwrap_or_default();
+            let _ = writeln!(
+                cases,
+                "      <skipped message=\"expected unsupported: {}\"/>",
+                escape(error.lines().next().unwrap_or_default())
+            );
+        }
+        if !phase.objects.is_empty() {
+            let _ = writeln!(
+                cases,
+                "      <system-out>{}</system-out>",
+                escape(&phase.objects.join("\n"))
+            );
+        }
+        cases.push_str("    </testcase>\n");
+    }
+    let mut tests = summary.phases.len();
+    if let Some(error) = &summary.error
+        && failures == 0
+    {
+        tests += 1;
+        failures += 1;
+        let _ = writeln!(
+            cases,
+            "    <testcase classname=\"runner\" name=\"run\" time=\"{:.3}\">\n      \
+             <failure message=\"{}\">{}</failure>\n    </testcase>",
+            summary.duration_secs,
+            escape(error.lines().next().unwrap_or_default()),
+            escape(error)
+        );
+    }
+
+    let warnings: Vec<_> = summary
+        .warnings
+        .iter()
+        .map(|w| format!("{}: {}: {}", w.source, w.title, w.message))
+        .collect();
+
+    let mut r = String::new();
+    r += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    let _ = writeln!(
+        r,
+        "<testsuites>\n  <testsuite name=\"fivetran\" tests=\"{tests}\" failures=\"{failures}\" \
+         skipped=\"{skipped}\" errors=\"0\" time=\"{:.3}\" timestamp=\"{}\">",
+        summary.duration_secs,
+        summary.started_at.format("%Y-%m-%dT%H:%M:%S")
+    );
+    r += &cases;
+    if !warnings.is_empty() {
+        let _ = writeln!(
+            r,
+            "    <system-err>{}</system-err>",
+            escape(&warnings.join("\n"))
+        );
+    }
+    r += "  </testsuite>\n</testsuites>\n";
+    r
+}
+
+/// Escapes text for XML, dropping characters XML cannot contain, such as
+/// terminal color codes.
+fn escape(s: &str) -> String {
+    let mut r = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => r += "&amp;",
+            '<' => r += "&lt;",
+            '>' => r += "&gt;",
+            '"' => r += "&quot;",
+            '\'' => r += "&apos;",
+            '\t' | '\n' | '\r' => r.push(c),
+            c if c.is_control() => {}
+            c => r.push(c),
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn scenario(report: &Report, infrastructure: bool) -> anyhow::Result<()> {
+        report
+            .scenario("basic", "wal", true, async {
+                let fail = async { anyhow::bail!("failed") };
+                if infrastructure {
+                    report.infrastructure("cleanup", fail).await
+                } else {
+                    report.phase("first sync", fail).await
+                }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn expected_failure_is_unsupported() {
+        let report = Report::new();
+        scenario(&report, false).await.unwrap();
+        assert_eq!(
+            report.scenarios.lock().unwrap()[0].outcome,
+            Outcome::Unsupported
+        );
+        assert_eq!(
+            report.phases.lock().unwrap()[0].outcome,
+            Outcome::Unsupported
+        );
+    }
+
+    #[tokio::test]
+    async fn infrastructure_failure_is_not_unsupported() {
+        let report = Report::new();
+        scenario(&report, true).await.unwrap_err();
+        assert_eq!(report.scenarios.lock().unwrap()[0].outcome, Outcome::Failed);
+        assert_eq!(report.phases.lock().unwrap()[0].outcome, Outcome::Failed);
+    }
+
+    #[tokio::test]
+    async fn failure_outside_phases_is_not_unsupported() {
+        let report = Report::new();
+        report
+            .scenario("basic", "wal", true, async { anyhow::bail!("failed") })
+            .await
+            .unwrap_err();
+        assert_eq!(report.scenarios.lock().unwrap()[0].outcome, Outcome::Failed);
+    }
+}
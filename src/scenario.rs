@@ -0,0 +1,497 @@
+//! Test scenarios, one per directory in `scenarios/`. Each has:
+//!
+//! - `dbschema/`, the Gel schema,
+//! - `setup.edgeql`, which fills the database,
+//! - optionally `mutate.edgeql`, which changes data after the first sync,
+//! - optionally `migrate.edgeql`, which changes the schema between syncs,
+//! - `scenario.toml`, with what not to sync, values that are known not to
+//!   arrive as they are, what the migration changes in the schema config and
+//!   optionally a change for checking incremental syncs,
+//! - `checks/`, with a query (`NAME.sql`) and its expected result
+//!   (`NAME.expected`) per check of the synced data, and the same for checks
+//!   after syncing the mutations in `checks/mutation/`, after syncing the
+//!   incremental change in `checks/incremental/` and after the migration in
+//!   `checks/evolution/<handling>/`, where `reload.expected` also has what
+//!   reloading the schema config reports as new. A check without an
+//!   `.expected` file is unverified: it is skipped with a warning until
+//!   `--bless` records what a sync with Fivetran returns.
+//!
+//! Adding a directory adds a scenario, without changes to the runner.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::fivetran::{SchemaChangeHandling, Skip};
+
+/// Which scenarios to run.
+#[derive(clap::Args)]
+pub struct SelectArgs {
+    /// Directory with a subdirectory per scenario
+    #[arg(long, value_name = "DIR", default_value = "scenarios")]
+    pub scenarios_dir: PathBuf,
+
+    /// Run this scenario instead of all of them. Can be repeated.
+    #[arg(long = "scenario", value_name = "NAME")]
+    pub scenarios: Vec<String>,
+}
+
+pub struct Scenario {
+    pub name: String,
+    pub dir: PathBuf,
+    pub config: Config,
+}
+
+/// Contents of `scenario.toml`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Schemas, tables or columns of the source that are not synced.
+    #[serde(default)]
+    pub skip: Vec<Skip>,
+
+    /// Columns that are not compared with their source.
+    #[serde(default)]
+    pub limitations: Vec<Limitation>,
+
+    /// From `checks/`.
+    #[serde(skip)]
+    pub checks: Vec<Check>,
+
+    /// Without `mutate.edgeql`, mutations are not applied.
+    #[serde(skip)]
+    pub mutation: Option<Mutation>,
+
+    /// Without this, incremental syncs are not checked.
+    pub incremental: Option<Incremental>,
+
+    /// Without this, the schema is not migrated.
+    pub evolution: Option<Evolutions>,
+}
+
+/// A column of the source whose values do not arrive in the destination as
+/// they are in Gel. It is still synced and its checks show what arrives, but
+/// it is not compared with its source.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limitation {
+    pub schema: String,
+    pub table: String,
+    pub column: String,
+    /// What happens to the values, for the report.
+    pub reason: String,
+}
+
+impl std::fmt::Display for Limitation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.schema, self.table, self.column)
+    }
+}
+
+/// A query over synced data and its expected result, from a `.sql` and
+/// `.expected` file.
+///
+/// The query can start with a `-- tables: SCHEMA.TABLE, ...` comment that
+/// lists the destination tables it reads. Checks without it look at the
+/// destination as a whole.
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// Path of the `.sql` file relative to `checks/`, without extension.
+    pub name: String,
+    pub tables: Vec<String>,
+    pub query: String,
+    /// Result of the query as text: a header with column names, then a line
+    /// per row with values separated by `, `. `None` if the `.expected` file
+    /// does not exist yet.
+    pub expected: Option<String>,
+    pub expected_file: PathBuf,
+}
+
+/// An expected result that does not come from a query, in a `.expected`
+/// file.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Path of the `.expected` file relative to `checks/`, without extension.
+    pub name: String,
+    /// `None` if the `.expected` file does not exist yet.
+    pub expected: Option<String>,
+    pub expected_file: PathBuf,
+}
+
+/// Checks of the destination after `mutate.edgeql` ran and was synced.
+#[derive(Debug)]
+pub struct Mutation {
+    pub checks: Vec<Check>,
+}
+
+/// What happens after `migrate.edgeql` with a new connection for each schema
+/// change handling. Only handlings with an `[evolution.<handling>]` table are
+/// tried.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Evolutions {
+    pub allow_all: Option<Evolution>,
+    pub allow_columns: Option<Evolution>,
+    pub block_all: Option<Evolution>,
+}
+
+/// What a connection reports and syncs after the migration.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Evolution {
+    /// New schemas, tables and columns in the reloaded schema config, a line
+    /// each, like `public.Person.nickname: enabled`, from
+    /// `checks/evolution/<handling>/reload.expected`.
+    #[serde(skip)]
+    pub reload: Snapshot,
+    /// From `checks/evolution/<handling>/`. Queries refer to the destination
+    /// schemas of the connection with `{prefix}`, e.g. `{prefix}_public.person`.
+    #[serde(skip)]
+    pub checks: Vec<Check>,
+}
+
+impl Evolutions {
+    pub fn iter(&self) -> impl Iterator<Item = (SchemaChangeHandling, &Evolution)> {
+        [
+            (SchemaChangeHandling::AllowAll, &self.allow_all),
+            (SchemaChangeHandling::AllowColumns, &self.allow_columns),
+            (SchemaChangeHandling::BlockAll, &self.block_all),
+        ]
+        .into_iter()
+        .filter_map(|(handling, evolution)| Some((handling, evolution.as_ref()?)))
+    }
+
+    fn get_mut(&mut self, handling: SchemaChangeHandling) -> Option<&mut Evolution> {
+        match handling {
+            SchemaChangeHandling::AllowAll => self.allow_all.as_mut(),
+            SchemaChangeHandling::AllowColumns => self.allow_columns.as_mut(),
+            SchemaChangeHandling::BlockAll => self.block_all.as_mut(),
+        }
+    }
+}
+
+impl Evolution {
+    /// Checks with `{prefix}` replaced by the schema prefix of the
+    /// connection.
+    pub fn checks(&self, prefix: &str) -> Vec<Check> {
+        self.checks
+            .iter()
+            .map(|c| Check {
+                tables: c
+                    .tables
+                    .iter()
+                    .map(|t| t.replace("{prefix}", prefix))
+                    .collect(),
+                query: c.query.replace("{prefix}", prefix),
+                ..c.clone()
+            })
+            .collect()
+    }
+}
+
+/// A change of Gel data that the next sync has to pick up.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Incremental {
+    /// EdgeQL that changes data.
+    pub change: String,
+    /// From `checks/incremental/`.
+    #[serde(skip)]
+    pub checks: Vec<Check>,
+}
+
+impl Scenario {
+    pub fn load(dir: &Path) -> anyhow::Result<Scenario> {
+        let name = dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow::anyhow!("invalid scenario directory {}", dir.display()))?
+            .to_string();
+        let path = dir.join("scenario.toml");
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", path.display()))?;
+        let config = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("invalid {}: {e}", path.display()))?;
+        let mut scenario = Scenario {
+            name,
+            dir: dir.to_path_buf(),
+            config,
+        };
+
+        let checks_dir = scenario.checks_dir();
+        scenario.config.checks = Check::load_dir(&checks_dir, &checks_dir)?;
+
+        let mutation_dir = checks_dir.join("mutation");
+        let mutate = scenario.mutate_file();
+        if mutate.is_file() {
+            scenario.config.mutation = Some(Mutation {
+                checks: Check::load_dir(&checks_dir, &mutation_dir)?,
+            });
+        } else {
+            anyhow::ensure!(
+                !mutation_dir.exists(),
+                "{} has checks of mutations, but there is no {}",
+                mutation_dir.display(),
+                mutate.display()
+            );
+        }
+
+        let incremental_dir = checks_dir.join("incremental");
+        match &mut scenario.config.incremental {
+            Some(incremental) => {
+                incremental.checks = Check::load_dir(&checks_dir, &incremental_dir)?
+            }
+            None => anyhow::ensure!(
+                !incremental_dir.exists(),
+                "{} has checks, but {} has no [incremental]",
+                incremental_dir.display(),
+                path.display()
+            ),
+        }
+
+        let migrate = scenario.migrate_file();
+        anyhow::ensure!(
+            scenario.config.evolution.is_none() || migrate.is_file(),
+            "{} has checks of a migration, but there is no {}",
+            path.display(),
+            migrate.display()
+        );
+        for handling in [
+            SchemaChangeHandling::AllowAll,
+            SchemaChangeHandling::AllowColumns,
+            SchemaChangeHandling::BlockAll,
+        ] {
+            let dir = checks_dir.join("evolution").join(handling.to_string());
+            let evolution = scenario
+                .config
+                .evolution
+                .as_mut()
+                .and_then(|e| e.get_mut(handling));
+            match evolution {
+                Some(evolution) => {
+                    evolution.checks = Check::load_dir(&checks_dir, &dir)?;
+                    evolution.reload = Snapshot::load(&checks_dir, &dir.join("reload.expected"))?;
+                }
+                None => anyhow::ensure!(
+                    !dir.exists(),
+                    "{} has checks, but {} has no [evolution.{handling}]",
+                    dir.display(),
+                    path.display()
+                ),
+            }
+        }
+        Ok(scenario)
+    }
+
+    /// Directory with the Gel schema.
+    pub fn schema_dir(&self) -> PathBuf {
+        self.dir.join("dbschema")
+    }
+
+    /// Directory with the checks of synced data.
+    pub fn checks_dir(&self) -> PathBuf {
+        self.dir.join("checks")
+    }
+
+    /// EdgeQL that fills the database.
+    pub fn setup_file(&self) -> PathBuf {
+        self.dir.join("setup.edgeql")
+    }
+
+    /// EdgeQL that changes data after the first sync.
+    pub fn mutate_file(&self) -> PathBuf {
+        self.dir.join("mutate.edgeql")
+    }
+
+    /// DDL that changes the schema between syncs.
+    pub fn migrate_file(&self) -> PathBuf {
+        self.dir.join("migrate.edgeql")
+    }
+}
+
+impl Check {
+    /// Loads the checks of all `.sql` files in `dir`, ordered by name, and
+    /// names them by their path relative to `checks_dir`.
+    fn load_dir(checks_dir: &Path, dir: &Path) -> anyhow::Result<Vec<Check>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", dir.display()))?;
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "sql") {
+                files.push(path);
+            }
+        }
+        files.sort();
+        files
+            .iter()
+            .map(|file| Check::load(checks_dir, file))
+            .collect()
+    }
+
+    fn load(checks_dir: &Path, file: &Path) -> anyhow::Result<Check> {
+        let query = std::fs::read_to_string(file)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", file.display()))?;
+        let tables = query
+            .lines()
+            .take_while(|line| line.starts_with("--"))
+            .filter_map(|line| line.strip_prefix("--")?.trim().strip_prefix("tables:"))
+            .flat_map(|tables| tables.split(','))
+            .map(|table| table.trim().to_string())
+            .filter(|table| !table.is_empty())
+            .collect();
+
+        let Snapshot {
+            name,
+            expected,
+            expected_file,
+        } = Snapshot::load(checks_dir, &file.with_extension("expected"))?;
+        Ok(Check {
+            name,
+            tables,
+            query,
+            expected,
+            expected_file,
+        })
+    }
+
+    /// Replaces the `.expected` file with `found`.
+    pub fn bless(&self, found: &str) -> anyhow::Result<()> {
+        bless(&self.expected_file, found)
+    }
+}
+
+impl Snapshot {
+    /// Reads `file`, if it exists, and names it by its path relative to
+    /// `checks_dir`.
+    fn load(checks_dir: &Path, file: &Path) -> anyhow::Result<Snapshot> {
+        let expected = match std::fs::read_to_string(file) {
+            Ok(expected) => Some(expected),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => anyhow::bail!("cannot read {}: {e}", file.display()),
+        };
+        let name = file
+            .strip_prefix(checks_dir)
+            .unwrap_or(file)
+            .with_extension("")
+            .to_string_lossy()
+            .into_owned();
+        Ok(Snapshot {
+            name,
+            expected,
+            expected_file: file.to_path_buf(),
+        })
+    }
+
+    /// Replaces the `.expected` file with `found`.
+    pub fn bless(&self, found: &str) -> anyhow::Result<()> {
+        bless(&self.expected_file, found)
+    }
+}
+
+fn bless(file: &Path, found: &str) -> anyhow::Result<()> {
+    let mut content = found.trim().to_string();
+    content.push('\n');
+    std::fs::write(file, content)
+        .map_err(|e| anyhow::anyhow!("cannot write {}: {e}", file.display()))
+}
+
+impl SelectArgs {
+    /// Loads the selected scenarios, or all of them, ordered by name.
+    pub fn load(&self) -> anyhow::Result<Vec<Scenario>> {
+        let mut names = self.scenarios.clone();
+        if names.is_empty() {
+            names = self.discover()?;
+        }
+        names.sort();
+        names.dedup();
+
+        let scenarios = names
+            .iter()
+            .map(|name| Scenario::load(&self.scenarios_dir.join(name)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::ensure!(
+            !scenarios.is_empty(),
+            "no scenarios in {}",
+            self.scenarios_dir.display()
+        );
+        Ok(scenarios)
+    }
+
+    /// Loads the only selected scenario, for commands that run just one.
+    pub fn load_one(&self) -> anyhow::Result<Scenario> {
+        let mut scenarios = self.load()?;
+        if scenarios.len() > 1 {
+            let names: Vec<_> = scenarios.iter().map(|s| s.name.as_str()).collect();
+            anyhow::bail!("pick one of the scenarios with --scenario: {names:?}");
+        }
+        Ok(scenarios.remove(0))
+    }
+
+    /// Names of all subdirectories with a `scenario.toml`.
+    fn discover(&self) -> anyhow::Result<Vec<String>> {
+        let dir = &self.scenarios_dir;
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| anyhow::anyhow!("cannot read {}: {e}", dir.display()))?;
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.path().join("scenario.toml").is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_scenarios_are_discovered() {
+        let select = SelectArgs {
+            scenarios_dir: "scenarios".into(),
+            scenarios: Vec::new(),
+        };
+        let names: Vec<_> = select.load().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["basic", "types"]);
+    }
+
+    #[test]
+    fn incremental_checks_are_loaded_from_their_directory() {
+        let basic = Scenario::load(Path::new("scenarios/basic")).unwrap();
+        let incremental = basic.config.incremental.unwrap();
+        let names: Vec<_> = incremental.checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["incremental/hello"]);
+        assert!(basic.config.checks.iter().all(|c| !c.name.contains('/')));
+    }
+
+    #[test]
+    fn evolution_handlings_expect_their_reload_in_a_file() {
+        let basic = Scenario::load(Path::new("scenarios/basic")).unwrap();
+        let evolution = basic.config.evolution.unwrap();
+        let reloads: Vec<_> = evolution
+            .iter()
+            .map(|(_, e)| e.reload.expected_file.clone())
+            .collect();
+        assert_eq!(
+            reloads,
+            [
+                Path::new("scenarios/basic/checks/evolution/allow_all/reload.expected"),
+                Path::new("scenarios/basic/checks/evolution/allow_columns/reload.expected"),
+                Path::new("scenarios/basic/checks/evolution/block_all/reload.expected"),
+            ]
+        );
+        assert_eq!(
+            evolution.allow_all.unwrap().reload.name,
+            "evolution/allow_all/reload"
+        );
+    }
+}
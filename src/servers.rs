@@ -0,0 +1,309 @@
+//! Gel and Postgres servers, and tunnels that make them reachable for
+//! Fivetran.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{self, Path};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::fivetran::{Endpoint, SchemaChangeHandling, Source, UpdateMethod};
+use crate::postgres;
+use crate::report::Report;
+use crate::scenario::Scenario;
+use crate::tunnel::{Monitored, Tunnel, TunnelArgs};
+
+/// Local servers, either started by us or already running.
+#[derive(clap::Args)]
+pub struct ServerArgs {
+    /// Use a running Gel server instead of starting one. Each scenario gets
+    /// a new branch of it, named like the scenario.
+    #[arg(long, value_name = "HOST:PORT")]
+    pub gel_addr: Option<SocketAddr>,
+
+    /// Use a running Postgres instead of starting one
+    #[arg(long, value_name = "HOST:PORT")]
+    pub postgres_addr: Option<SocketAddr>,
+
+    #[command(flatten)]
+    pub tunnel: TunnelArgs,
+}
+
+pub enum Gel {
+    Captive(gel_captive::ServerProcess),
+    External { addr: SocketAddr, branch: String },
+}
+
+pub enum Postgres {
+    Captive(gel_pg_captive::PostgresProcess),
+    External(SocketAddr),
+}
+
+/// Servers with tunnels. Captive servers stop when this is dropped.
+pub struct Servers {
+    pub gel: Gel,
+    pub postgres: Postgres,
+    /// How Fivetran reaches Gel.
+    pub gel_endpoint: Endpoint,
+    /// How Fivetran reaches Postgres.
+    pub postgres_endpoint: Endpoint,
+    /// How the connection detects changes in Gel.
+    pub update_method: UpdateMethod,
+    _tunnels: [Monitored; 2],
+}
+
+impl Servers {
+    /// Starts servers with a fresh Gel instance or branch for `scenario`,
+    /// with its schema and data, and prepares Gel for `update_method`.
+    /// `tunnel` is built from `args` once for all scenarios. Starting each
+    /// server and opening each tunnel is a phase of the report.
+    pub async fn start(
+        args: &ServerArgs,
+        tunnel: &Arc<dyn Tunnel>,
+        scenario: &Scenario,
+        update_method: UpdateMethod,
+        report: &Report,
+    ) -> anyhow::Result<Servers> {
+        let gel = report.infrastructure("start gel", async {
+            let gel = match args.gel_addr {
+                Some(addr) => Gel::External {
+                    addr,
+                    branch: scenario.name.clone(),
+                },
+                None => Gel::Captive(start_gel_server(scenario.schema_dir()).await),
+            };
+            gel.setup(scenario)?;
+            Ok(gel)
+        });
+        let postgres = report.infrastructure("start postgres", async {
+            Ok(match args.postgres_addr {
+                Some(addr) => Postgres::External(addr),
+                None => Postgres::Captive(start_postgres().await),
+            })
+        });
+        let (postgres, gel) = tokio::join!(postgres, gel);
+        let (postgres, gel) = (postgres?, gel?);
+        log::info!("postgres_addr = {:?}", postgres.addr());
+        log::info!("gel_addr = {:?}", gel.addr());
+
+        if update_method.replication_plugin().is_some() {
+            report
+                .phase(
+                    "set up replication",
+                    postgres::setup_replication(gel.addr(), gel.database(), update_method),
+                )
+                .await?;
+        }
+
+        let postgres_tunnel = report
+            .infrastructure(
+                format!("open {} tunnel to postgres", tunnel.name()),
+                tunnel.clone().open_monitored(postgres.addr()),
+            )
+            .await?;
+        let gel_tunnel = report
+            .infrastructure(
+                format!("open {} tunnel to gel", tunnel.name()),
+                tunnel.clone().open_monitored(gel.addr()),
+            )
+            .await?;
+
+        Ok(Servers {
+            gel,
+            postgres,
+            gel_endpoint: gel_tunnel.endpoint.clone(),
+            postgres_endpoint: postgres_tunnel.endpoint.clone(),
+            update_method,
+            _tunnels: [gel_tunnel, postgres_tunnel],
+        })
+    }
+
+    /// What Fivetran syncs from Gel for `scenario`, into `gel_*` schemas and
+    /// without picking up schema changes.
+    pub fn source<'a>(&'a self, scenario: &'a Scenario) -> Source<'a> {
+        Source {
+            endpoint: &self.gel_endpoint,
+            database: self.gel.database(),
+            skip: &scenario.config.skip,
+            update_method: self.update_method,
+            schema_prefix: "gel",
+            schema_change_handling: SchemaChangeHandling::BlockAll,
+        }
+    }
+}
+
+impl Gel {
+    pub fn addr(&self) -> SocketAddr {
+        match self {
+            Gel::Captive(server) => {
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), server.info.port)
+            }
+            Gel::External { addr, .. } => *addr,
+        }
+    }
+
+    /// Postgres database that Fivetran syncs from, which is the branch.
+    pub fn database(&self) -> &str {
+        match self {
+            Gel::Captive(_) => "main",
+            Gel::External { branch, .. } => branch,
+        }
+    }
+
+    /// Runs a query with the Gel CLI.
+    pub fn query(&self, query: &str) -> anyhow::Result<()> {
+        let status = self.cli(true).arg("query").arg(query).status()?;
+        anyhow::ensure!(status.success(), "gel query failed: {query}");
+        Ok(())
+    }
+
+    /// Runs the queries in a file with the Gel CLI.
+    pub fn query_file(&self, path: &Path) -> anyhow::Result<()> {
+        let status = self
+            .cli(true)
+            .arg("query")
+            .arg("--file")
+            .arg(path)
+            .status()?;
+        anyhow::ensure!(status.success(), "gel query failed: {}", path.display());
+        Ok(())
+    }
+
+    /// Fills the database with the data of `scenario`. A running server
+    /// first gets a new branch with the schema of the scenario; a captive
+    /// one was started with it.
+    fn setup(&self, scenario: &Scenario) -> anyhow::Result<()> {
+        if let Gel::External { branch, .. } = self {
+            log::info!("creating gel branch {branch}");
+            // the branch is left over from an earlier run, if this succeeds
+            let _ = self
+                .cli(false)
+                .args(["branch", "drop", "--non-interactive", "--force", branch])
+                .status();
+            let status = self
+                .cli(false)
+                .args(["branch", "create", "--empty", branch])
+                .status()?;
+            anyhow::ensure!(status.success(), "cannot create gel branch {branch}");
+            self.query(&migration(&scenario.schema_dir())?)?;
+        }
+
+        self.query_file(&scenario.setup_file())
+    }
+
+    /// Gel CLI connected to the server, and to the branch if `branch`.
+    fn cli(&self, branch: bool) -> Command {
+        match self {
+            Gel::Captive(server) => server.cli(),
+            Gel::External { addr, branch: name } => {
+                let mut cli = Command::new("gel");
+                cli.arg("--host")
+                    .arg(addr.ip().to_string())
+                    .arg("--port")
+                    .arg(addr.port().to_string())
+                    .arg("--tls-security")
+                    .arg("insecure");
+                if branch {
+                    cli.arg("--branch").arg(name);
+                }
+                cli
+            }
+        }
+    }
+}
+
+/// A migration to the schema in the `.gel` files of `dir`, as one query.
+fn migration(dir: &Path) -> anyhow::Result<String> {
+    let mut files = std::fs::read_dir(dir)?
+        .map(|e| Ok(e?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.retain(|f| f.extension().is_some_and(|e| e == "gel"));
+    files.sort();
+
+    let mut schema = String::new();
+    for file in files {
+        schema += &std::fs::read_to_string(&file)?;
+        schema += "\n";
+    }
+    Ok(format!(
+        "start migration to {{\n{schema}}};\npopulate migration;\ncommit migration;"
+    ))
+}
+
+impl Postgres {
+    pub fn addr(&self) -> SocketAddr {
+        match self {
+            Postgres::Captive(process) => process.tcp_address,
+            Postgres::External(addr) => *addr,
+        }
+    }
+}
+
+async fn start_postgres() -> gel_pg_captive::PostgresProcess {
+    tokio::task::spawn_blocking(|| {
+        gel_pg_captive::PostgresBuilder::new()
+            .auth(gel_auth::AuthType::Trust)
+            .with_automatic_mode(gel_pg_captive::Mode::TcpSsl)
+            // .server_option("log_statement", "all")
+            .with_automatic_bin_path()
+            .unwrap()
+            .build()
+            .unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+async fn start_gel_server(schema_dir: path::PathBuf) -> gel_captive::ServerProcess {
+    let server = tokio::task::spawn_blocking(|| {
+        gel_captive::ServerBuilder::new()
+            .log_file_path(Some(
+                path::PathBuf::from_str("./target/gel-server.log").unwrap(),
+            ))
+            .start()
+    })
+    .await
+    .unwrap();
+
+    server.apply_schema(&schema_dir);
+    server
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::scenario::SelectArgs;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        servers: ServerArgs,
+    }
+
+    #[tokio::test]
+    #[ignore = "needs gel-server and postgres binaries"]
+    async fn start_twice_with_local_bore() {
+        let args = Cli::parse_from(["runner", "--tunnel", "local-bore"]).servers;
+        let scenario = SelectArgs {
+            scenarios_dir: "scenarios".into(),
+            scenarios: vec!["basic".to_string()],
+        }
+        .load_one()
+        .unwrap();
+        let tunnel = args.tunnel.build().unwrap();
+        let report = Report::new();
+        for _ in 0..2 {
+            let servers = Servers::start(&args, &tunnel, &scenario, UpdateMethod::Xmin, &report)
+                .await
+                .unwrap();
+            for endpoint in [&servers.gel_endpoint, &servers.postgres_endpoint] {
+                let Endpoint::Direct(addr) = endpoint else {
+                    panic!("local bore gave {endpoint}");
+                };
+                tokio::net::TcpStream::connect(addr).await.unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Ways of making local servers reachable for Fivetran.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, str::FromStr};
+
+use futures::future::BoxFuture;
+use rand::Rng;
+
+use crate::fivetran::{Endpoint, SshTunnel};
+
+/// A way of making a local server reachable for Fivetran.
+pub trait Tunnel: Send + Sync + 'static {
+    /// Short name, for logs.
+    fn name(&self) -> &'static str;
+
+    /// Makes `local_addr` reachable. When reopening a dropped tunnel,
+    /// `previous` is the endpoint it had. It should be kept if possible,
+    /// because Fivetran objects are already configured with it.
+    fn open<'a>(
+        &'a self,
+        local_addr: SocketAddr,
+        previous: Option<&'a Endpoint>,
+    ) -> BoxFuture<'a, anyhow::Result<Opened>>;
+
+    /// Opens the tunnel and monitors it: whenever it drops, for example
+    /// during a long sync, it is reopened. Monitoring stops when the
+    /// returned handle is dropped.
+    fn open_monitored(
+        self: Arc<Self>,
+        local_addr: SocketAddr,
+    ) -> BoxFuture<'static, anyhow::Result<Monitored>> {
+        Box::pin(async move {
+            let opened = self.open(local_addr, None).await?;
+            let endpoint = opened.endpoint.clone();
+            log::info!(
+                "{} tunnel: {local_addr} is reachable at {endpoint}",
+                self.name()
+            );
+            let task = tokio::spawn(monitor(self, local_addr, opened));
+            Ok(Monitored { endpoint, task })
+        })
+    }
+}
+
+pub struct Opened {
+    pub endpoint: Endpoint,
+    /// Forwards connections and resolves when the tunnel drops. `None` for
+    /// tunnels that need nothing running on our side.
+    pub forward: Option<BoxFuture<'static, anyhow::Result<()>>>,
+}
+
+/// An open tunnel. Closes when dropped.
+pub struct Monitored {
+    pub endpoint: Endpoint,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for Monitored {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn monitor<T: Tunnel + ?Sized>(tunnel: Arc<T>, local_addr: SocketAddr, mut opened: Opened) {
+    let name = tunnel.name();
+    loop {
+        let Some(forward) = opened.forward.take() else {
+            return;
+        };
+        match forward.await {
+            Ok(()) => log::warn!("{name} tunnel to {local_addr} closed"),
+            Err(e) => log::warn!("{name} tunnel to {local_addr} dropped: {e:#}"),
+        }
+
+        let previous = opened.endpoint;
+        let mut delay = Duration::from_secs(1);
+        opened = loop {
+            match tunnel.open(local_addr, Some(&previous)).await {
+                Ok(opened) => break opened,
+                Err(e) => {
+                    log::warn!("cannot reopen {name} tunnel to {local_addr}: {e:#}");
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(Duration::from_secs(30));
+                }
+            }
+        };
+        if opened.endpoint != previous {
+            log::error!(
+                "{name} tunnel to {local_addr} moved from {previous} to {}, \
+                 Fivetran cannot reach it anymore",
+                opened.endpoint
+            );
+        } else {
+            log::info!("{name} tunnel to {local_addr} reopened");
+        }
+    }
+}
+
+/// Selection of a tunnel from command line flags.
+#[derive(clap::Args)]
+pub struct TunnelArgs {
+    /// How Fivetran reaches the local servers
+    #[arg(long, value_enum, default_value_t = TunnelKind::Bore)]
+    pub tunnel: TunnelKind,
+
+    /// IP of the bore server, for `bore` [default: $BORE_SERVER_IP]
+    #[arg(long, value_name = "IP")]
+    pub bore_server: Option<IpAddr>,
+
+    /// Secret of the bore server, for `bore` [default: $BORE_SERVER_SECRET]
+    #[arg(long, value_name = "SECRET")]
+    pub bore_secret: Option<String>,
+
+    /// IP under which Fivetran reaches this host, for `direct` and
+    /// `local-bore`, or the servers as seen from the SSH host, for `ssh`
+    #[arg(long, value_name = "IP")]
+    pub public_ip: Option<IpAddr>,
+
+    /// SSH host that Fivetran tunnels through, for `ssh`. It has to accept
+    /// the public key of the Fivetran group, which is logged during setup.
+    #[arg(long, value_name = "USER@HOST[:PORT]")]
+    pub ssh: Option<SshTunnel>,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum TunnelKind {
+    /// Through a bore server
+    Bore,
+    /// Through a bore server that we start ourselves
+    LocalBore,
+    /// No tunnel, the servers are reachable as they are
+    Direct,
+    /// Through an SSH host, by Fivetran itself
+    Ssh,
+}
+
+impl TunnelArgs {
+    /// Builds the selected tunnel. It is meant to be built once per run and
+    /// shared by all servers, since `local-bore` starts a server of its own.
+    pub fn build(&self) -> anyhow::Result<Arc<dyn Tunnel>> {
+        Ok(match self.tunnel {
+            TunnelKind::Bore => {
+                let server = match self.bore_server {
+                    Some(server) => server,
+                    None => IpAddr::from_str(&env::var("BORE_SERVER_IP").map_err(|_| {
+                        anyhow::anyhow!("bore tunnel needs --bore-server or BORE_SERVER_IP")
+                    })?)?,
+                };
+                let secret = self
+                    .bore_secret
+                    .clone()
+                    .or_else(|| env::var("BORE_SERVER_SECRET").ok());
+                Arc::new(Bore {
+                    server: server.to_string(),
+                    secret,
+                    public_ip: server,
+                })
+            }
+            TunnelKind::LocalBore => Arc::new(LocalBore::start(
+                self.public_ip.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            )),
+            TunnelKind::Direct => Arc::new(Direct {
+                public_ip: self.public_ip,
+            }),
+            TunnelKind::Ssh => Arc::new(Ssh {
+                tunnel: self
+                    .ssh
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("ssh tunnel needs --ssh"))?,
+                target_ip: self.public_ip,
+            }),
+        })
+    }
+}
+
+/// Forwards through a [bore](https://github.com/ekzhang/bore) server.
+pub struct Bore {
+    /// Host of the bore server's control port.
+    server: String,
+    secret: Option<String>,
+    /// IP under which Fivetran reaches ports of the bore server.
+    public_ip: IpAddr,
+}
+
+impl Tunnel for Bore {
+    fn name(&self) -> &'static str {
+        "bore"
+    }
+
+    fn open<'a>(
+        &'a self,
+        local_addr: SocketAddr,
+        previous: Option<&'a Endpoint>,
+    ) -> BoxFuture<'a, anyhow::Result<Opened>> {
+        Box::pin(async move {
+            // 0 lets the server pick a port
+            let port = match previous {
+                Some(Endpoint::Direct(addr)) => addr.port(),
+                _ => 0,
+            };
+            let client = bore_cli::client::Client::new(
+                &local_addr.ip().to_string(),
+                local_addr.port(),
+                &self.server,
+                port,
+                self.secret.as_deref(),
+            )
+            .await?;
+            let endpoint = Endpoint::Direct(SocketAddr::new(self.public_ip, client.remote_port()));
+            Ok(Opened {
+                endpoint,
+                forward: Some(Box::pin(client.listen())),
+            })
+        })
+    }
+}
+
+/// Starts a bore server in this process and forwards through it, for
+/// testing without any infrastructure. The server listens on the fixed
+/// control port of bore, so there can only be one at a time. It stops when
+/// this is dropped.
+pub struct LocalBore {
+    bore: Bore,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl LocalBore {
+    pub fn start(public_ip: IpAddr) -> Self {
+        let secret: String = rand::rng()
+            .sample_iter(rand::distr::Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        let server_secret = secret.clone();
+        let server = tokio::spawn(async move {
+            let server = bore_cli::server::Server::new(1024..=65535, Some(&server_secret));
+            if let Err(e) = server.listen().await {
+                log::error!("local bore server: {e:#}");
+            }
+        });
+
+        LocalBore {
+            bore: Bore {
+                server: Ipv4Addr::LOCALHOST.to_string(),
+                secret: Some(secret),
+                public_ip,
+            },
+            server,
+        }
+    }
+}
+
+impl Drop for LocalBore {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl Tunnel for LocalBore {
+    fn name(&self) -> &'static str {
+        "local-bore"
+    }
+
+    fn open<'a>(
+        &'a self,
+        local_addr: SocketAddr,
+        previous: Option<&'a Endpoint>,
+    ) -> BoxFuture<'a, anyhow::Result<Opened>> {
+        Box::pin(async move {
+            // the server might still be starting
+            let mut attempts = 0;
+            loop {
+                match self.bore.open(local_addr, previous).await {
+                    Err(e) if attempts < 20 => {
+                        log::debug!("local bore server is not ready: {e:#}");
+                        attempts += 1;
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    res => return res,
+                }
+            }
+        })
+    }
+}
+
+/// No tunnel, for servers that Fivetran can reach directly.
+pub struct Direct {
+    /// Replaces the IP of local addresses.
+    public_ip: Option<IpAddr>,
+}
+
+impl Tunnel for Direct {
+    fn name(&self) -> &'static str {
+        "direct"
+    }
+
+    fn open<'a>(
+        &'a self,
+        local_addr: SocketAddr,
+        _previous: Option<&'a Endpoint>,
+    ) -> BoxFuture<'a, anyhow::Result<Opened>> {
+        let ip = self.public_ip.unwrap_or(local_addr.ip());
+        let endpoint = Endpoint::Direct(SocketAddr::new(ip, local_addr.port()));
+        Box::pin(async move {
+            Ok(Opened {
+                endpoint,
+                forward: None,
+            })
+        })
+    }
+}
+
+/// Fivetran's own SSH tunnel: Fivetran connects to the SSH host and from
+/// there to the server.
+pub struct Ssh {
+    tunnel: SshTunnel,
+    /// IP of the servers as seen from the SSH host, if it differs from the
+    /// local one.
+    target_ip: Option<IpAddr>,
+}
+
+impl Tunnel for Ssh {
+    fn name(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn open<'a>(
+        &'a self,
+        local_addr: SocketAddr,
+        _previous: Option<&'a Endpoint>,
+    ) -> BoxFuture<'a, anyhow::Result<Opened>> {
+        let ip = self.target_ip.unwrap_or(local_addr.ip());
+        let endpoint = Endpoint::SshTunnel {
+            tunnel: self.tunnel.clone(),
+            addr: SocketAddr::new(ip, local_addr.port()),
+        };
+        Box::pin(async move {
+            Ok(Opened {
+                endpoint,
+                forward: None,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+
+    /// Forwards a connection through `tunnel` to `listener`.
+    async fn forward(tunnel: &Arc<dyn Tunnel>, listener: &TcpListener) {
+        let monitored = tunnel
+            .clone()
+            .open_monitored(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let Endpoint::Direct(addr) = monitored.endpoint else {
+            panic!("local bore gave {}", monitored.endpoint);
+        };
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test]
+    async fn local_bore_is_reusable_and_restartable() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        for _ in 0..2 {
+            let tunnel: Arc<dyn Tunnel> =
+                Arc::new(LocalBore::start(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+            // like the tunnels of two scenarios
+            forward(&tunnel, &listener).await;
+            forward(&tunnel, &listener).await;
+            drop(tunnel);
+            // let the aborted server release the control port
+            tokio::task::yield_now().await;
+        }
+    }
+}